@@ -1,7 +1,15 @@
 use anyhow::bail;
+use clap::error::ErrorKind;
+use clap::parser::ValueSource;
+use clap::CommandFactory;
 use clap::Parser;
 use clap::ValueEnum;
+use regex::Regex;
+use std::ffi::OsString;
+use std::fs;
+use std::io;
 
+use crate::config::Config;
 use crate::THREAD_LOCAL_STATE;
 use anyhow::Result;
 
@@ -27,49 +35,932 @@ impl LogLevel {
             _ => None,
         }
     }
+
+    /// The level for one of Python's numeric levels, like `10` for `logging.DEBUG`.
+    ///
+    /// `exception` has no number of its own, since it logs at `40`, like `error`.
+    pub fn from_number(number: u32) -> Option<LogLevel> {
+        match number {
+            10 => Some(Self::Debug),
+            20 => Some(Self::Info),
+            30 => Some(Self::Warning),
+            40 => Some(Self::Error),
+            50 => Some(Self::Critical),
+            _ => None,
+        }
+    }
+
+    /// The logger method name for this level
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Debug => "debug",
+            Self::Info => "info",
+            Self::Warning => "warning",
+            Self::Error => "error",
+            Self::Exception => "exception",
+            Self::Critical => "critical",
+        }
+    }
+
+    /// The level used for threshold comparisons.
+    ///
+    /// `logger.exception` logs at ERROR level in Python, so it shouldn't
+    /// rank above `logger.error` when deciding what to convert.
+    pub fn threshold(self) -> LogLevel {
+        match self {
+            Self::Exception => Self::Error,
+            level => level,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Copy, Clone, Eq, ValueEnum)]
+pub enum LoggerFlavor {
+    Stdlib,
+    Loguru,
+}
+
+impl LoggerFlavor {
+    /// The placeholder each argument moved out of the string is replaced with.
+    ///
+    /// loguru formats messages with `str.format` rather than printf-style
+    /// interpolation, so it wants `{}` where the standard library wants `%s`.
+    /// Conversions and format specs are carried over as long as printf-style
+    /// formatting has an equivalent, so `{:02f}` becomes `%02f` and `{!r}` becomes `%r`.
+    /// Anything else, and arguments without either, get the `--placeholder`.
+    pub fn placeholder(self, config: &Config, conversion: Option<char>, spec: &str) -> String {
+        match self {
+            Self::Stdlib if conversion.is_none() && spec.is_empty() => config.placeholder.clone(),
+            Self::Stdlib => format_spec_to_printf(&config.printf_spec_regex, conversion, spec)
+                .unwrap_or_else(|| config.placeholder.clone()),
+            Self::Loguru => {
+                let mut placeholder = "{".to_string();
+                if let Some(c) = conversion {
+                    placeholder.push('!');
+                    placeholder.push(c);
+                }
+                if !spec.is_empty() {
+                    placeholder.push(':');
+                    placeholder.push_str(spec);
+                }
+                placeholder.push('}');
+                placeholder
+            }
+        }
+    }
+}
+
+// Captures the parts of a format spec that printf-style formatting could have an equivalent for:
+// fill, alignment, sign, zero-padding, width, precision and type
+pub(crate) const PRINTF_SPEC_REGEX: &str =
+    r"^(?:(.)?([<>^=]))?([-+ ])?(0)?([0-9]*)(\.[0-9]+)?([dfeEgGxXos])?$";
+
+// Matches a printf-style conversion at the start of a string, like `%s`, `%-5d` or `%(name)r`.
+// The space flag is left out, so the `% d` in "50% done" isn't mistaken for one.
+pub(crate) const PRINTF_CONVERSION_REGEX: &str =
+    r"^%(\([^)]*\))?[-#0+]*([0-9]+|\*)?(\.([0-9]+|\*))?[diouxXeEfFgGcrsa%]";
+
+/// Translate a `str.format` conversion and spec to a printf-style conversion,
+/// like `!r` to `%r`, `<5.2f` to `%-5.2f` or `+05d` to `%+05d`.
+///
+/// Only left and right alignment padded with spaces, the sign, zero-padding, width and
+/// precision are translated. printf-style formatting can't center, fill with other
+/// characters or pad after the sign, so `^`, `*<` and `=` aren't.
+/// Without an explicit type, width needs an alignment and precision isn't translated,
+/// since `{:5}` pads strings and numbers differently and `{:.2}` isn't `%.2s` for numbers.
+fn format_spec_to_printf(re: &Regex, conversion: Option<char>, spec: &str) -> Option<String> {
+    let caps = re.captures(spec)?;
+    let fill = caps.get(1).map(|m| m.as_str());
+    let align = caps.get(2).map(|m| m.as_str());
+    let sign = caps.get(3).map_or("", |m| m.as_str());
+    let zero = caps.get(4).is_some();
+    let width = caps.get(5).map_or("", |m| m.as_str());
+    let precision = caps.get(6).map_or("", |m| m.as_str());
+
+    if !matches!(fill, None | Some(" ")) || !matches!(align, None | Some("<" | ">")) {
+        return None;
+    }
+    let left_align = align == Some("<");
+
+    let kind = match (conversion, caps.get(7).map(|m| m.as_str())) {
+        (Some(c @ ('r' | 'a')), None | Some("s")) => c.to_string(),
+        (Some('r' | 'a'), Some(_)) => return None,
+        (_, Some(kind)) => kind.to_string(),
+        (_, None) if precision.is_empty() && (align.is_some() || width.is_empty()) => {
+            "s".to_string()
+        }
+        _ => return None,
+    };
+    let string_kind = matches!(kind.as_str(), "s" | "r" | "a");
+    // `{:<05d}` pads with zeros on the right, and zero-padding strings isn't portable
+    if zero && (left_align || string_kind) {
+        return None;
+    }
+    // Python refuses a sign for strings, and without a type we can't tell what's formatted
+    if !sign.is_empty() && string_kind {
+        return None;
+    }
+
+    let mut flags = String::new();
+    if left_align {
+        flags.push('-');
+    }
+    // `-` is the default, and printf-style formatting has no flag for it
+    if sign != "-" {
+        flags.push_str(sign);
+    }
+    if zero {
+        flags.push('0');
+    }
+    Some(format!("%{flags}{width}{precision}{kind}"))
 }
 
-pub fn emit_error(reason: &str) {
-    eprintln!(
-        "{reason}. Please open an issue at https://github.com/snok/printf-log-formatter/issues/new"
+/// Whether a format spec centers its value, like `^10`, which printf-style formatting can't do
+fn is_centered(re: &Regex, spec: &str) -> bool {
+    re.captures(spec)
+        .and_then(|caps| caps.get(2))
+        .map_or(false, |m| m.as_str() == "^")
+}
+
+/// Point out centered values replaced with a plain placeholder, since they lose their padding
+pub(crate) fn warn_centered_spec(config: &Config, spec: &str, lineno: usize) {
+    if config.quiet || !is_centered(&config.printf_spec_regex, spec) {
+        return;
+    }
+    let filename = THREAD_LOCAL_STATE.with(|tl| tl.filename.clone());
+    emit!(
+        "Centered values can't be expressed with printf-style formatting, \
+         so `{{:{spec}}}` in `{filename}` line {lineno} is replaced with `{}`",
+        config.placeholder
     );
 }
 
+/// Check that a `--placeholder` is a single printf-style conversion, like `%s` or `%-10r`
+fn parse_placeholder(placeholder: &str) -> Result<String, String> {
+    let re = Regex::new(r"^%[-#0 +]*[0-9]*(\.[0-9]+)?[diouxXeEfFgGcrsa]$").unwrap();
+    if re.is_match(placeholder) {
+        Ok(placeholder.to_string())
+    } else {
+        Err("expected a single printf-style conversion, like `%s` or `%r`".to_string())
+    }
+}
+
+/// The levels loggers called on a receiver are handled at, from `--receiver-levels`
+#[derive(Debug, PartialEq, Clone, Eq)]
+pub struct ReceiverLevels {
+    pub receiver: String,
+    pub levels: Vec<LogLevel>,
+}
+
+/// Renders as it's passed on the command line, like `metrics=error:critical`
+impl std::fmt::Display for ReceiverLevels {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let levels = self.levels.iter().map(|level| level.as_str());
+        write!(
+            f,
+            "{}={}",
+            self.receiver,
+            levels.collect::<Vec<_>>().join(":")
+        )
+    }
+}
+
+/// Parse a log level given as a name, like `debug`, or as Python's number for it, like `10`
+fn parse_log_level(value: &str) -> Result<LogLevel, String> {
+    LogLevel::maybe_from_str(value)
+        .or_else(|| value.parse().ok().and_then(LogLevel::from_number))
+        .ok_or_else(|| format!("unknown log level `{value}`"))
+}
+
+/// Parse a `--receiver-levels` entry, like `metrics=error:critical`
+fn parse_receiver_levels(value: &str) -> Result<ReceiverLevels, String> {
+    let Some((receiver, levels)) = value.split_once('=') else {
+        return Err(
+            "expected a receiver and its levels, like `metrics=error:critical`".to_string(),
+        );
+    };
+    let levels = levels
+        .split(':')
+        .map(parse_log_level)
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(ReceiverLevels {
+        receiver: receiver.to_string(),
+        levels,
+    })
+}
+
+#[derive(Debug, PartialEq, Copy, Clone, Eq, ValueEnum)]
+pub enum QuoteStyle {
+    Single,
+    Double,
+}
+
+impl QuoteStyle {
+    pub fn char(self) -> char {
+        match self {
+            Self::Single => '\'',
+            Self::Double => '"',
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Copy, Clone, Eq, ValueEnum)]
+pub enum OutputFormat {
+    Text,
+    Checkstyle,
+    Json,
+}
+
+pub(crate) const DEFAULT_ISSUE_URL: &str =
+    "https://github.com/snok/printf-log-formatter/issues/new";
+
+/// Report something that went wrong on our end, asking for an issue at `--issue-url`
+pub fn emit_error(config: &Config, reason: &str) {
+    // Files we fail to handle are left as they are, so this isn't worth interrupting `--quiet` for
+    if config.quiet {
+        return;
+    }
+    emit!("{}", error_message(reason, &config.issue_url));
+}
+
+/// An error message, followed by where to report it unless `--issue-url` is empty
+pub(crate) fn error_message(reason: &str, issue_url: &str) -> String {
+    if issue_url.is_empty() {
+        reason.to_string()
+    } else {
+        format!("{reason}. Please open an issue at {issue_url}")
+    }
+}
+
+/// Report a logger left alone on purpose, like one containing an expression we can't convert
+pub fn emit_skip(config: &Config, reason: &str) {
+    if config.quiet {
+        return;
+    }
+    emit!("{reason}");
+}
+
+/// The prefix and quote character of the string literal starting at `col_offset`,
+/// like `("rf", '"')` for `rf"{x}"`
+fn string_start(string: &str, col_offset: usize) -> Option<(String, char)> {
+    // Skip past any parentheses wrapping the string, like in `("{}").format(x)`
+    let chars = string
+        .chars()
+        .skip(col_offset)
+        .skip_while(|c| *c == '(' || c.is_whitespace());
+
+    let mut prefix = String::new();
+    for c in chars {
+        match c {
+            '\'' | '"' => return Some((prefix, c)),
+            'f' | 'F' | 'r' | 'R' | 'u' | 'U' | 'b' | 'B' if prefix.len() < 2 => prefix.push(c),
+            _ => return None,
+        }
+    }
+    None
+}
+
 pub fn get_char(string: &str, col_offset: usize) -> Result<char> {
-    if let Some(c) = string.chars().nth(col_offset) {
-        return match c {
-            '\'' => Ok('\''),
-            '"' => Ok('"'),
-            'f' => Ok(string.chars().nth(col_offset + 1).unwrap()),
-            _ => bail!("noo"),
-        };
+    match string_start(string, col_offset) {
+        Some((_, quote)) => Ok(quote),
+        None => bail!("Failed to infer quotes"),
     }
-    emit_error("Failed to infer quote character");
-    bail!("Failed to inherit quotes")
 }
 
-pub fn get_quotes(lineno: usize, col_offset: usize) -> Result<char> {
+pub fn get_quotes(config: &Config, lineno: usize, col_offset: usize) -> Result<char> {
     let content = THREAD_LOCAL_STATE.with(|tl| tl.content.clone());
     let vec_content = content.split('\n').map(str::to_owned).collect::<Vec<_>>();
 
     if let Ok(t) = get_char(&vec_content[lineno - 1], col_offset) {
         Ok(t)
     } else {
+        // Messages that aren't string literals, like `TEMPLATE.format(x)`, can't be converted
         let filename = THREAD_LOCAL_STATE.with(|tl| tl.filename.clone());
-        emit_error(&format!(
-            "Failed to infer quote from `{filename}` line {lineno}"
-        ));
+        emit_skip(
+            config,
+            &format!(
+                "Skipping the logger in `{filename}` line {lineno}, since its message doesn't start with a string"
+            ),
+        );
         bail!("Failed to infer quote")
     }
 }
 
+/// Whether the string literal starting at this location is a raw string, like `r"\d+ {}"`
+pub fn is_raw_string(lineno: usize, col_offset: usize) -> bool {
+    THREAD_LOCAL_STATE.with(|tl| {
+        tl.content
+            .split('\n')
+            .nth(lineno - 1)
+            .and_then(|line| string_start(line, col_offset))
+            .map_or(false, |(prefix, _)| prefix.contains(['r', 'R']))
+    })
+}
+
 #[derive(Parser, Debug, Clone)]
 #[command(author, version, about, long_about = "Printf log formatter")]
 #[command(next_line_help = true)]
 pub struct Opts {
-    #[arg(value_enum, short, long, default_value_t = LogLevel::Error)]
+    /// The lowest log level to handle, by name or number, like `error` or `40`
+    #[arg(short, long, default_value = "error", value_parser = parse_log_level)]
     pub log_level: LogLevel,
 
-    #[arg(required = true)]
+    /// Only handle these log levels, like `--levels debug,info`. Overrides `--log-level`
+    #[arg(long, value_delimiter = ',', value_parser = parse_log_level)]
+    pub levels: Vec<LogLevel>,
+
+    /// The logging library used. loguru does its own brace-style formatting,
+    /// so f-strings and `str.format` calls are converted to `{}` placeholders.
+    #[arg(value_enum, long, default_value_t = LoggerFlavor::Stdlib)]
+    pub logger_flavor: LoggerFlavor,
+
+    /// Spread rewritten loggers over several lines when they exceed this length
+    #[arg(long)]
+    pub line_length: Option<usize>,
+
+    /// Indent arguments of spread out loggers by this many spaces.
+    /// Detected from the file's first indented line by default
+    #[arg(long)]
+    pub indent_width: Option<usize>,
+
+    /// Indent arguments of spread out loggers with tabs
+    #[arg(long, conflicts_with = "indent_width")]
+    pub use_tabs: bool,
+
+    /// Quote style for rewritten messages. Inferred from the original string by default
+    #[arg(value_enum, long)]
+    pub quote: Option<QuoteStyle>,
+
+    /// Placeholder for arguments without a conversion or format spec, like `%r`.
+    /// Must be a single printf-style conversion
+    #[arg(long, default_value = "%s", value_parser = parse_placeholder)]
+    pub placeholder: String,
+
+    /// Also rewrite module-level `logging.<level>()` calls. Off by default
+    #[arg(long)]
+    pub include_module_logging: bool,
+
+    /// Only handle loggers called on names and attributes, like `self.logger.error()`,
+    /// and not ones looked up by index, like `LOGGERS["app"].error()`, or logger methods
+    /// called through a name they were assigned to, like `log_error = logger.error`
+    #[arg(long)]
+    pub strict_loggers: bool,
+
+    /// Only handle loggers called on these receivers at the given levels, like
+    /// `--receiver-levels metrics=error:critical` to leave `metrics.info()` alone.
+    /// Matched against the name the level is called on, like `metrics` in `self.metrics.info()`
+    #[arg(long, value_delimiter = ',', value_parser = parse_receiver_levels)]
+    pub receiver_levels: Vec<ReceiverLevels>,
+
+    /// Position of the message among the logger's arguments, for wrappers taking
+    /// something else first, like `audit_log.error(request, f"{x}")`
+    #[arg(long, default_value_t = 0)]
+    pub message_arg_index: usize,
+
+    /// Rewrite `print(f"...")` and `print("...".format())` calls into logger calls at this level
+    #[arg(long, value_parser = parse_log_level)]
+    pub convert_print: Option<LogLevel>,
+
+    /// Name of the logger `print()` calls are converted to, when using `--convert-print`
+    #[arg(long, default_value = "logger")]
+    pub print_logger_name: String,
+
+    /// Rewrite f-string assert messages to `str.format()` calls, for consistency with loggers.
+    /// Asserts can't defer formatting, so the message is still formatted when the assert fails
+    #[arg(long)]
+    pub convert_asserts: bool,
+
+    /// Rewrite f-strings among the values of a logger's `extra` dict to printf-style `%`
+    /// expressions, for consistency. The logger doesn't format `extra`, so they're still eager
+    #[arg(long)]
+    pub convert_extra: bool,
+
+    /// Rename the deprecated `logger.warn()` to `logger.warning()` in the loggers converted
+    #[arg(long)]
+    pub rename_warn: bool,
+
+    /// Add a `# was: ...` comment with the original message to each converted line
+    #[arg(long)]
+    pub annotate_conversions: bool,
+
+    /// Rewrite f-string messages to `str.format()` calls instead of printf-style arguments,
+    /// for consistency without moving to lazy formatting. `str.format()` calls are left alone
+    #[arg(long)]
+    pub format_only_normalize: bool,
+
+    /// Turn logger messages that are f-strings without placeholders, like `f"constant"`,
+    /// into plain strings. They're left alone otherwise
+    #[arg(long)]
+    pub strip_useless_fstrings: bool,
+
+    /// Don't write any files, only report the loggers that would be changed
+    #[arg(long, alias = "no-write")]
+    pub check: bool,
+
+    /// Print each change as it would be made, with the old and new message, for reviewing
+    /// conversions. Implies `--check`
+    #[arg(long, conflicts_with = "output_format")]
+    pub preview: bool,
+
+    /// Write fixed files under this directory instead of in place, keeping their paths
+    /// relative to the directory they were found in
+    #[arg(long)]
+    pub output_dir: Option<String>,
+
+    /// Also write files without anything to fix under `--output-dir`
+    #[arg(long, requires = "output_dir")]
+    pub copy_unchanged: bool,
+
+    /// Exit with code 2 when a file can't be parsed, instead of skipping it
+    #[arg(long)]
+    pub error_on_parse_failure: bool,
+
+    /// Parse the fixed content of each file again before writing it, and leave files
+    /// alone when it doesn't parse, exiting with code 2
+    #[arg(long)]
+    pub validate: bool,
+
+    /// Stop once this many files fail to parse, exiting with code 2. Unlimited by default
+    #[arg(long)]
+    pub max_failures: Option<usize>,
+
+    /// Show how many files have been processed so far. Only shown when stderr is a terminal
+    #[arg(long)]
+    pub progress: bool,
+
+    /// Where to report internal errors, mentioned alongside them.
+    /// Pass an empty string to leave it out
+    #[arg(long, default_value = DEFAULT_ISSUE_URL)]
+    pub issue_url: String,
+
+    /// Print the name of each file written, and details like the messages given other quotes
+    #[arg(short, long)]
+    pub verbose: bool,
+
+    /// Only print hard errors. The exit code is unaffected
+    #[arg(short, long, conflicts_with = "verbose")]
+    pub quiet: bool,
+
+    /// How changes are reported. Text output is only printed with `--check`
+    #[arg(value_enum, long, default_value_t = OutputFormat::Text)]
+    pub output_format: OutputFormat,
+
+    /// Include this many lines before and after each change in `--output-format json`
+    #[arg(long, default_value_t = 0)]
+    pub report_context: usize,
+
+    /// Write how many loggers were converted and skipped at each level to this file, as JSON
+    #[arg(long)]
+    pub stats_json: Option<String>,
+
+    /// Print the time spent reading, parsing, detecting changes, applying them and writing,
+    /// summed across files, to stderr
+    #[arg(long)]
+    pub profile: bool,
+
+    /// Point out arguments moved out of messages that involve calls.
+    /// These are evaluated even when the logger is disabled, so might be worth guarding
+    #[arg(long)]
+    pub warn_eager_args: bool,
+
+    /// Point out arguments moved out of messages that call any of these functions or methods,
+    /// like `--warn-side-effects pop,next`. Their side effects now happen at a different point
+    #[arg(long, value_delimiter = ',')]
+    pub warn_side_effects: Vec<String>,
+
+    /// Point out f-string and `str.format()` loggers left alone for being below the log level,
+    /// or not at one of `--levels`. Their messages are still formatted even when the logger
+    /// is disabled
+    #[arg(long)]
+    pub warn_below_threshold: bool,
+
+    /// Point out expressions moved out of a message more than once, like `x` in `f"{x} {x}"`.
+    /// The output is unchanged, since each placeholder needs its own argument
+    #[arg(long)]
+    pub warn_duplicate_args: bool,
+
+    /// Copy arguments we don't know how to reconstruct straight from the source,
+    /// instead of leaving their logger alone
+    #[arg(long)]
+    pub force_parse_fallback: bool,
+
+    /// Leave messages alone when their text already contains printf-style placeholders,
+    /// like `f"{x} took %s"`, instead of keeping them next to the converted ones
+    #[arg(long)]
+    pub respect_existing_percent: bool,
+
+    /// Only handle files matching any of these globs, like `--include 'src/**/*.py'`
+    #[arg(long, value_delimiter = ',')]
+    pub include: Vec<String>,
+
+    /// Skip files matching any of these globs, like `--exclude '**/migrations/*'`.
+    /// Takes precedence over `--include`
+    #[arg(long, value_delimiter = ',')]
+    pub exclude: Vec<String>,
+
+    /// Print the options as they will be used, then exit without handling any files
+    #[arg(long)]
+    pub print_config: bool,
+
+    /// Read a file's content from stdin and print the fixed content to stdout, instead of
+    /// handling files. The name is used in messages. Exits with 1 when the content changed
+    #[arg(long, conflicts_with_all = ["filenames", "check"])]
+    pub stdin_filename: Option<String>,
+
+    /// Python files, or directories to search for them
+    #[arg(required_unless_present_any = ["print_config", "stdin_filename"])]
     pub filenames: Vec<String>,
 }
+
+impl Opts {
+    /// Parse the command line, taking the options it doesn't set from the
+    /// `[tool.printf-log-formatter]` table in `pyproject.toml`, in the current directory.
+    pub fn parse_with_pyproject() -> Result<Self, clap::Error> {
+        let args = std::env::args_os().collect::<Vec<_>>();
+        match fs::read_to_string("pyproject.toml") {
+            Ok(pyproject) => Self::parse_with_config(args, &pyproject),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Self::try_parse_from(args),
+            Err(err) => Err(Self::command().error(
+                ErrorKind::Io,
+                format!("Failed to read `pyproject.toml`: {err}"),
+            )),
+        }
+    }
+
+    /// Parse the command line, with defaults from a `pyproject.toml`.
+    ///
+    /// Keys are the options' long names, like `log-level = "warning"`, the same as
+    /// `--print-config` prints. Options given on the command line win, and replace lists
+    /// like `exclude` rather than adding to them.
+    fn parse_with_config(args: Vec<OsString>, pyproject: &str) -> Result<Self, clap::Error> {
+        let command = Self::command();
+        let invalid = |message: String| command.clone().error(ErrorKind::InvalidValue, message);
+        let pyproject = pyproject
+            .parse::<toml::Value>()
+            .map_err(|err| invalid(format!("Failed to parse `pyproject.toml`: {err}")))?;
+        let table = match pyproject
+            .get("tool")
+            .and_then(|tool| tool.get("printf-log-formatter"))
+        {
+            Some(toml::Value::Table(table)) => table,
+            Some(_) => {
+                return Err(invalid(
+                    "`tool.printf-log-formatter` should be a table".into(),
+                ))
+            }
+            None => return Self::try_parse_from(args),
+        };
+
+        // Options from the file go before the command line, unless it sets them itself
+        let matches = command.clone().try_get_matches_from(&args)?;
+        let mut merged = args[..1].to_vec();
+        for (key, value) in table {
+            let id = key.replace('-', "_");
+            let known = command
+                .get_arguments()
+                .any(|arg| arg.get_id() == id.as_str() && arg.get_long().is_some());
+            if !known {
+                return Err(invalid(format!(
+                    "Unknown option `{key}` in `pyproject.toml`"
+                )));
+            }
+            if matches.value_source(&id) == Some(ValueSource::CommandLine) {
+                continue;
+            }
+            let values = match value {
+                toml::Value::Array(values) => values.iter().collect(),
+                // Flags can't be turned off again, so `false` is the same as leaving them out
+                toml::Value::Boolean(false) => vec![],
+                value => vec![value],
+            };
+            for value in values {
+                merged.push(match value {
+                    toml::Value::Boolean(true) => format!("--{key}").into(),
+                    toml::Value::String(value) => format!("--{key}={value}").into(),
+                    toml::Value::Integer(value) => format!("--{key}={value}").into(),
+                    _ => {
+                        return Err(invalid(format!(
+                            "Unsupported value for `{key}` in `pyproject.toml`"
+                        )))
+                    }
+                });
+            }
+        }
+        merged.extend_from_slice(&args[1..]);
+        Self::try_parse_from(merged)
+    }
+
+    /// Check combinations of options that clap can't tell apart from their defaults
+    pub fn validate(&self) -> Result<(), clap::Error> {
+        // loguru messages already use the placeholders `str.format()` takes
+        if self.format_only_normalize && self.logger_flavor == LoggerFlavor::Loguru {
+            return Err(Self::command().error(
+                ErrorKind::ArgumentConflict,
+                "`--format-only-normalize` can't be used with `--logger-flavor loguru`",
+            ));
+        }
+        Ok(())
+    }
+
+    /// Whether loggers at this level should be handled, according to
+    /// `--levels` when given, and the `--log-level` threshold otherwise.
+    pub fn handles_level(&self, level: LogLevel) -> bool {
+        if self.levels.is_empty() {
+            level.threshold() >= self.log_level.threshold()
+        } else {
+            self.levels.contains(&level)
+        }
+    }
+
+    /// Whether `--receiver-levels` lets loggers called on this receiver be handled at this level.
+    /// Receivers that aren't mentioned are handled at any level.
+    pub fn receiver_handles_level(&self, receiver: &str, level: LogLevel) -> bool {
+        let mut entries = self
+            .receiver_levels
+            .iter()
+            .filter(|entry| entry.receiver == receiver)
+            .peekable();
+        entries.peek().is_none() || entries.any(|entry| entry.levels.contains(&level))
+    }
+
+    /// Render the options in TOML, one per line, for `--print-config`.
+    /// Options that aren't set, like `--line-length` by default, are left out.
+    pub fn render_config(&self) -> String {
+        let mut lines = vec![format!(
+            "# printf-log-formatter {}",
+            env!("CARGO_PKG_VERSION")
+        )];
+        let mut push = |key: &str, value: String| lines.push(format!("{key} = {value}"));
+
+        push("log-level", toml_string(value_name(&self.log_level)));
+        push("levels", toml_array(self.levels.iter().map(value_name)));
+        push(
+            "logger-flavor",
+            toml_string(value_name(&self.logger_flavor)),
+        );
+        if let Some(line_length) = self.line_length {
+            push("line-length", line_length.to_string());
+        }
+        if let Some(indent_width) = self.indent_width {
+            push("indent-width", indent_width.to_string());
+        }
+        push("use-tabs", self.use_tabs.to_string());
+        if let Some(quote) = &self.quote {
+            push("quote", toml_string(value_name(quote)));
+        }
+        push("placeholder", toml_string(self.placeholder.clone()));
+        push(
+            "include-module-logging",
+            self.include_module_logging.to_string(),
+        );
+        push("strict-loggers", self.strict_loggers.to_string());
+        push(
+            "receiver-levels",
+            toml_array(self.receiver_levels.iter().map(ToString::to_string)),
+        );
+        push("message-arg-index", self.message_arg_index.to_string());
+        if let Some(level) = &self.convert_print {
+            push("convert-print", toml_string(value_name(level)));
+        }
+        push(
+            "print-logger-name",
+            toml_string(self.print_logger_name.clone()),
+        );
+        push("convert-asserts", self.convert_asserts.to_string());
+        push("convert-extra", self.convert_extra.to_string());
+        push("rename-warn", self.rename_warn.to_string());
+        push(
+            "annotate-conversions",
+            self.annotate_conversions.to_string(),
+        );
+        push(
+            "format-only-normalize",
+            self.format_only_normalize.to_string(),
+        );
+        push(
+            "strip-useless-fstrings",
+            self.strip_useless_fstrings.to_string(),
+        );
+        push("check", self.check.to_string());
+        push("preview", self.preview.to_string());
+        if let Some(output_dir) = &self.output_dir {
+            push("output-dir", toml_string(output_dir.clone()));
+        }
+        push("copy-unchanged", self.copy_unchanged.to_string());
+        push(
+            "error-on-parse-failure",
+            self.error_on_parse_failure.to_string(),
+        );
+        push("validate", self.validate.to_string());
+        if let Some(max_failures) = self.max_failures {
+            push("max-failures", max_failures.to_string());
+        }
+        push("progress", self.progress.to_string());
+        push("issue-url", toml_string(self.issue_url.clone()));
+        push("verbose", self.verbose.to_string());
+        push("quiet", self.quiet.to_string());
+        push(
+            "output-format",
+            toml_string(value_name(&self.output_format)),
+        );
+        push("report-context", self.report_context.to_string());
+        if let Some(path) = &self.stats_json {
+            push("stats-json", toml_string(path.clone()));
+        }
+        push("profile", self.profile.to_string());
+        push("warn-eager-args", self.warn_eager_args.to_string());
+        push(
+            "warn-side-effects",
+            toml_array(self.warn_side_effects.iter().cloned()),
+        );
+        push(
+            "warn-below-threshold",
+            self.warn_below_threshold.to_string(),
+        );
+        push("warn-duplicate-args", self.warn_duplicate_args.to_string());
+        push(
+            "force-parse-fallback",
+            self.force_parse_fallback.to_string(),
+        );
+        push(
+            "respect-existing-percent",
+            self.respect_existing_percent.to_string(),
+        );
+        push("include", toml_array(self.include.iter().cloned()));
+        push("exclude", toml_array(self.exclude.iter().cloned()));
+        if let Some(filename) = &self.stdin_filename {
+            push("stdin-filename", toml_string(filename.clone()));
+        }
+        push("filenames", toml_array(self.filenames.iter().cloned()));
+
+        lines.push(String::new());
+        lines.join("\n")
+    }
+}
+
+/// The name a value is given on the command line, like `warning` for `LogLevel::Warning`
+fn value_name<T: ValueEnum>(value: &T) -> String {
+    value
+        .to_possible_value()
+        .map(|value| value.get_name().to_string())
+        .unwrap_or_default()
+}
+
+fn toml_string(value: String) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+fn toml_array(values: impl Iterator<Item = String>) -> String {
+    format!(
+        "[{}]",
+        values.map(toml_string).collect::<Vec<_>>().join(", ")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_spec_to_printf() {
+        let re = Regex::new(PRINTF_SPEC_REGEX).unwrap();
+        #[rustfmt::skip]
+        let test_cases = [
+            // Alignment, with an optional space fill
+            (None, "<5", Some("%-5s")),
+            (None, ">5", Some("%5s")),
+            (None, " <5", Some("%-5s")),
+            (None, " >5d", Some("%5d")),
+            // Sign
+            (None, "+d", Some("%+d")),
+            (None, "-d", Some("%d")),
+            (None, " d", Some("% d")),
+            (None, "<+8.2f", Some("%-+8.2f")),
+            // Zero-padding
+            (None, "05d", Some("%05d")),
+            (None, "+05d", Some("%+05d")),
+            (None, ">05.1f", Some("%05.1f")),
+            (Some('r'), "<10", Some("%-10r")),
+            // Centering, other fills and padding after the sign
+            (None, "^10", None),
+            (None, " ^10d", None),
+            (None, "*<10", None),
+            (None, "0>5d", None),
+            (None, "=+5d", None),
+            // Signs for strings, or values without a type
+            (None, "+", None),
+            (None, "+5s", None),
+            (Some('r'), "+", None),
+            // Zero-padding on the right, or for strings
+            (None, "<05d", None),
+            (None, ">05", None),
+        ];
+        for (conversion, spec, expected) in test_cases {
+            assert_eq!(
+                format_spec_to_printf(&re, conversion, spec).as_deref(),
+                expected,
+                "{spec}"
+            );
+        }
+
+        assert!(is_centered(&re, "^10"));
+        assert!(is_centered(&re, "*^10d"));
+        assert!(!is_centered(&re, "<10"));
+        assert!(!is_centered(&re, ""));
+    }
+
+    #[test]
+    fn test_parse_log_level() {
+        let test_cases = [
+            ("10", LogLevel::Debug),
+            ("20", LogLevel::Info),
+            ("30", LogLevel::Warning),
+            ("40", LogLevel::Error),
+            ("50", LogLevel::Critical),
+            ("debug", LogLevel::Debug),
+            ("warn", LogLevel::Warning),
+            ("exception", LogLevel::Exception),
+        ];
+        for (value, expected) in test_cases {
+            assert_eq!(parse_log_level(value), Ok(expected), "{value}");
+        }
+        for value in ["0", "15", "60", "-10", "loud"] {
+            assert_eq!(
+                parse_log_level(value),
+                Err(format!("unknown log level `{value}`"))
+            );
+        }
+
+        let opts = Opts::parse_from([
+            "printf-log-formatter",
+            "--log-level",
+            "20",
+            "--levels",
+            "30,critical",
+            "a.py",
+        ]);
+        assert_eq!(opts.log_level, LogLevel::Info);
+        assert_eq!(opts.levels, vec![LogLevel::Warning, LogLevel::Critical]);
+    }
+
+    #[test]
+    fn test_parse_with_config() {
+        let pyproject = r#"
+[tool.black]
+line-length = 100
+
+[tool.printf-log-formatter]
+log-level = "warning"
+line-length = 88
+exclude = ["migrations/*"]
+convert-extra = true
+use-tabs = false
+"#;
+        let args = |args: &[&str]| {
+            ["printf-log-formatter"]
+                .iter()
+                .chain(args)
+                .map(OsString::from)
+                .collect()
+        };
+
+        let opts = Opts::parse_with_config(args(&["a.py"]), pyproject).unwrap();
+        assert_eq!(opts.log_level, LogLevel::Warning);
+        assert_eq!(opts.line_length, Some(88));
+        assert_eq!(opts.exclude, vec!["migrations/*"]);
+        assert!(opts.convert_extra);
+        assert!(!opts.use_tabs);
+        assert_eq!(opts.filenames, vec!["a.py"]);
+
+        // The command line wins, and lists are replaced rather than added to
+        let opts = Opts::parse_with_config(
+            args(&["-l", "info", "--exclude", "tests/*", "a.py"]),
+            pyproject,
+        )
+        .unwrap();
+        assert_eq!(opts.log_level, LogLevel::Info);
+        assert_eq!(opts.line_length, Some(88));
+        assert_eq!(opts.exclude, vec!["tests/*"]);
+
+        let opts = Opts::parse_with_config(args(&["a.py"]), "[tool.black]\n").unwrap();
+        assert_eq!(opts.log_level, LogLevel::Error);
+
+        for pyproject in [
+            "[tool.printf-log-formatter]\nlog-levels = \"info\"\n",
+            "[tool.printf-log-formatter]\nfilenames = [\"a.py\"]\n",
+            "[tool.printf-log-formatter]\nlog-level = \"loud\"\n",
+            "[tool.printf-log-formatter]\nline-length = 8.5\n",
+            "[tool.printf-log-formatter\n",
+        ] {
+            assert!(
+                Opts::parse_with_config(args(&["a.py"]), pyproject).is_err(),
+                "{pyproject}"
+            );
+        }
+
+        // Mistakes on the command line are returned too, with or without the table
+        for pyproject in [pyproject, "[tool.black]\n"] {
+            assert!(Opts::parse_with_config(args(&["--loud", "a.py"]), pyproject).is_err());
+        }
+    }
+}
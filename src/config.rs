@@ -0,0 +1,42 @@
+use std::ops::Deref;
+
+use regex::Regex;
+
+use crate::cli::{Opts, PRINTF_CONVERSION_REGEX, PRINTF_SPEC_REGEX};
+use crate::parse_format::{FORMATTED_VALUE_GROUP_REGEX, FORMATTED_VALUE_REGEX};
+
+/// Options, along with everything that can be prepared from them up front.
+///
+/// A config is built once and shared by reference between all the files
+/// being processed, including across threads. It dereferences to the options.
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub opts: Opts,
+    pub(crate) printf_spec_regex: Regex,
+    pub(crate) printf_conversion_regex: Regex,
+    pub(crate) formatted_value_regex: Regex,
+    pub(crate) formatted_value_group_regex: Regex,
+}
+
+impl Config {
+    pub fn from_opts(opts: &Opts) -> Self {
+        let mut opts = opts.clone();
+        // Previews are for reviewing changes before making them
+        opts.check |= opts.preview;
+        Self {
+            opts,
+            printf_spec_regex: Regex::new(PRINTF_SPEC_REGEX).unwrap(),
+            printf_conversion_regex: Regex::new(PRINTF_CONVERSION_REGEX).unwrap(),
+            formatted_value_regex: Regex::new(FORMATTED_VALUE_REGEX).unwrap(),
+            formatted_value_group_regex: Regex::new(FORMATTED_VALUE_GROUP_REGEX).unwrap(),
+        }
+    }
+}
+
+impl Deref for Config {
+    type Target = Opts;
+
+    fn deref(&self) -> &Opts {
+        &self.opts
+    }
+}
@@ -0,0 +1,257 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+
+/// A Python file to handle, and the path it's mirrored at under `--output-dir`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PythonFile {
+    pub filename: String,
+    /// The path relative to the directory it was found in. Files passed directly keep the
+    /// path they were given, unless it's absolute or leaves the current directory. Then
+    /// it's only their name.
+    pub relative: PathBuf,
+}
+
+/// Find the Python files to process among the paths given on the command line.
+///
+/// Directories are searched recursively, skipping hidden ones like `.git`, and `skip_dir`,
+/// which is where `--output-dir` writes files. Files are kept when they match any of the
+/// `include` globs, or when there are none, unless they match any of the `exclude` globs;
+/// exclude wins. Globs are matched against paths relative to the directory searched, and
+/// paths passed as files are matched as they were given. Symlinked directories aren't
+/// followed while searching.
+///
+/// Fails when a glob is invalid.
+pub fn find_python_files(
+    paths: &[String],
+    include: &[String],
+    exclude: &[String],
+    skip_dir: Option<&str>,
+) -> Result<Vec<PythonFile>> {
+    let (include, exclude) = (Globs::new(include)?, Globs::new(exclude)?);
+    let skip_dir = skip_dir.and_then(|dir| fs::canonicalize(dir).ok());
+    let mut files = vec![];
+    for path in paths {
+        let path = Path::new(path);
+        if path.is_dir() {
+            walk_dir(path, path, skip_dir.as_deref(), &mut files);
+        } else if is_python_file(path) {
+            files.push((
+                path.to_path_buf(),
+                path.strip_prefix("./").unwrap_or(path).to_path_buf(),
+            ));
+        }
+    }
+
+    Ok(files
+        .into_iter()
+        .filter(|(_, relative)| {
+            (include.is_empty() || include.is_match(relative)) && !exclude.is_match(relative)
+        })
+        .map(|(path, relative)| {
+            let inside = relative
+                .components()
+                .all(|component| matches!(component, Component::Normal(_) | Component::CurDir));
+            PythonFile {
+                filename: path.to_string_lossy().into_owned(),
+                relative: if inside {
+                    relative
+                } else {
+                    PathBuf::from(path.file_name().unwrap_or_default())
+                },
+            }
+        })
+        .collect())
+}
+
+/// Where each file is written with `--output-dir`, like `out/pkg/a.py` for `pkg/a.py`
+/// found in a directory passed on the command line.
+///
+/// Fails when two files would be written to the same path, like `a/x.py` and `b/x.py`
+/// found in `a` and `b`.
+pub fn output_paths(output_dir: &str, files: &[PythonFile]) -> Result<Vec<PathBuf>> {
+    let mut written = HashMap::new();
+    let mut paths = vec![];
+    for file in files {
+        let path = Path::new(output_dir).join(&file.relative);
+        if let Some(other) = written.insert(path.clone(), &file.filename) {
+            bail!(
+                "`{other}` and `{}` would both be written to `{}`",
+                file.filename,
+                path.display()
+            );
+        }
+        paths.push(path);
+    }
+    Ok(paths)
+}
+
+fn is_python_file(path: &Path) -> bool {
+    path.extension()
+        .map_or(false, |ext| ext.eq_ignore_ascii_case("py"))
+}
+
+/// Collect the Python files under a directory, along with their path relative to `root`
+fn walk_dir(root: &Path, dir: &Path, skip_dir: Option<&Path>, files: &mut Vec<(PathBuf, PathBuf)>) {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(err) => {
+            emit!("Failed to read `{}`: {err}", dir.display());
+            return;
+        }
+    };
+    // Directory entries come in no particular order
+    let mut paths = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .collect::<Vec<_>>();
+    paths.sort();
+
+    for path in paths {
+        let hidden = path
+            .file_name()
+            .map_or(false, |name| name.to_string_lossy().starts_with('.'));
+        // Symlinked directories could lead back up the tree, and be searched forever
+        if path.is_dir() && path.is_symlink() {
+            continue;
+        }
+        if path.is_dir() {
+            let skipped = skip_dir.map_or(false, |skip_dir| {
+                fs::canonicalize(&path).map_or(false, |path| path == skip_dir)
+            });
+            if !hidden && !skipped {
+                walk_dir(root, &path, skip_dir, files);
+            }
+        } else if is_python_file(&path) {
+            let relative = path.strip_prefix(root).unwrap_or(&path).to_path_buf();
+            files.push((path, relative));
+        }
+    }
+}
+
+/// Globs to match paths against, like `src/**/*.py`.
+///
+/// `*` and `?` match within a path segment, and a `**` segment matches any number of them.
+/// Globs without a `/`, like `test_*.py`, are matched against the file name.
+struct Globs {
+    paths: GlobSet,
+    names: GlobSet,
+}
+
+impl Globs {
+    fn new(globs: &[String]) -> Result<Self> {
+        let (mut paths, mut names) = (GlobSetBuilder::new(), GlobSetBuilder::new());
+        for glob in globs {
+            let compiled = GlobBuilder::new(glob)
+                .literal_separator(true)
+                .build()
+                .with_context(|| format!("Invalid glob `{glob}`"))?;
+            if glob.contains('/') {
+                paths.add(compiled);
+            } else {
+                names.add(compiled);
+            }
+        }
+        Ok(Self {
+            paths: paths.build()?,
+            names: names.build()?,
+        })
+    }
+
+    fn is_empty(&self) -> bool {
+        self.paths.is_empty() && self.names.is_empty()
+    }
+
+    fn is_match(&self, path: &Path) -> bool {
+        self.paths.is_match(path)
+            || path
+                .file_name()
+                .map_or(false, |name| self.names.is_match(name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_globs() {
+        let cases = [
+            ("src/**/*.py", "src/a.py", true),
+            ("src/**/*.py", "src/pkg/sub/a.py", true),
+            ("src/**/*.py", "tests/a.py", false),
+            ("src/*.py", "src/pkg/a.py", false),
+            ("test_*.py", "tests/test_a.py", true),
+            ("test_?.py", "test_ab.py", false),
+            ("**/migrations/*", "app/migrations/0001.py", true),
+            ("*.py", "a.pyi", false),
+        ];
+        for (glob, path, expected) in cases {
+            let globs = Globs::new(&[glob.to_string()]).unwrap();
+            assert_eq!(globs.is_match(Path::new(path)), expected, "{glob} {path}");
+        }
+
+        // Matching doesn't blow up on globs with many stars
+        let globs = Globs::new(&["**/*a*a*a*a*a*a*a*a*b/**/*.py".to_string()]).unwrap();
+        let path = format!("{}/x.py", vec!["a".repeat(40); 20].join("/"));
+        assert!(!globs.is_match(Path::new(&path)));
+
+        assert!(Globs::new(&["src/[a.py".to_string()]).is_err());
+    }
+
+    #[test]
+    fn test_output_paths() {
+        let dir = std::env::temp_dir()
+            .join("printf-log-formatter-tests")
+            .join("files_output_paths");
+        let _ = fs::remove_dir_all(&dir);
+        for file in ["a/pkg/x.py", "b/x.py", "c.py", "a/out/y.py"] {
+            let path = dir.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, "").unwrap();
+        }
+        let arg = |path: &str| dir.join(path).to_string_lossy().into_owned();
+        let output_dir = arg("a/out");
+
+        // Directories are mirrored from where they're searched, skipping the output directory
+        let files =
+            find_python_files(&[arg("a"), arg("c.py")], &[], &[], Some(&output_dir)).unwrap();
+        let relative = files.iter().map(|file| &file.relative).collect::<Vec<_>>();
+        assert_eq!(relative, [Path::new("pkg/x.py"), Path::new("c.py")]);
+        assert_eq!(
+            output_paths("out", &files).unwrap(),
+            [PathBuf::from("out/pkg/x.py"), PathBuf::from("out/c.py")]
+        );
+
+        // Files passed by an absolute path only keep their name
+        let files = find_python_files(&[arg("c.py")], &[], &[], None).unwrap();
+        assert_eq!(files[0].relative, Path::new("c.py"));
+
+        // Files can't overwrite each other
+        let files = find_python_files(&[arg("a/pkg"), arg("b")], &[], &[], None).unwrap();
+        let error = output_paths("out", &files).unwrap_err().to_string();
+        assert!(
+            error.ends_with("would both be written to `out/x.py`"),
+            "{error}"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_symlinked_dirs_are_skipped() {
+        let dir = std::env::temp_dir()
+            .join("printf-log-formatter-tests")
+            .join("files_symlinks");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("pkg")).unwrap();
+        fs::write(dir.join("pkg/a.py"), "").unwrap();
+        std::os::unix::fs::symlink(&dir, dir.join("pkg/loop")).unwrap();
+
+        let files =
+            find_python_files(&[dir.to_string_lossy().into_owned()], &[], &[], None).unwrap();
+        let relative = files.iter().map(|file| &file.relative).collect::<Vec<_>>();
+        assert_eq!(relative, [Path::new("pkg/a.py")]);
+    }
+}
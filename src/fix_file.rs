@@ -1,99 +1,660 @@
-use crate::gen_visitor::walk_stmt;
-use crate::visitor::LoggerVisitor;
-use crate::{Change, THREAD_LOCAL_STATE};
-use anyhow::Result;
+use crate::cli::{error_message, Opts};
+use crate::config::Config;
+use crate::gen_visitor::Visitor;
+use crate::stats::Stats;
+use crate::timings::Timings;
+use crate::visitor::{
+    is_file_skipped, logger_method_aliases, logging_module_names, skipped_rows, LoggerVisitor,
+};
+use crate::{Change, ParseFailure, ThreadLocal, THREAD_LOCAL_STATE};
+use anyhow::{bail, Context, Result};
+use rayon::prelude::*;
 use rustpython_parser::parse_program;
-use tokio::fs::File;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+use tokio::fs::{self, File};
 use tokio::io::AsyncWriteExt;
 
-pub(crate) async fn fix_file() -> Result<bool> {
+// Indentation used for arguments when a logger is spread over several lines,
+// if the file doesn't have any indented lines to go by
+const REFLOW_INDENT: &str = "    ";
+
+/// Fix all loggers in a file and write the result back to it, or to `output`, its path
+/// under `--output-dir` from `output_paths`. With `--check`, nothing is written.
+///
+/// Returns the changes found, how many loggers were converted and skipped, and the time
+/// spent on each phase after reading the file.
+pub async fn fix_file(
+    filename: String,
+    content: String,
+    output: Option<PathBuf>,
+    config: &Config,
+) -> Result<(Vec<Change>, Stats, Timings)> {
+    let state = ThreadLocal::new(filename, content);
+    THREAD_LOCAL_STATE
+        .scope(state, write_changes(output, config))
+        .await
+}
+
+async fn write_changes(
+    output: Option<PathBuf>,
+    config: &Config,
+) -> Result<(Vec<Change>, Stats, Timings)> {
     // Load thread-local state
     let state = THREAD_LOCAL_STATE.with(Clone::clone);
+    let mut timings = Timings::default();
 
     // Find changes needing to be made
-    let changes = get_changes(&state.content, &state.filename);
+    let found = find_changes_with_timings(&state.content, &state.filename, config, &mut timings);
+    let (changes, stats) = match found {
+        Some(found) => found,
+        None if config.error_on_parse_failure || config.max_failures.is_some() => {
+            return Err(ParseFailure {
+                filename: state.filename,
+            }
+            .into())
+        }
+        None => (vec![], Stats::default()),
+    };
 
     // Write changes to string content
-    let (content, content_changed) = change_content(&state.content, changes);
+    let start = Instant::now();
+    let new_content = fixed_content(&state.content, &state.filename, &changes, config)?;
+    timings.apply = start.elapsed();
 
-    // Write updated content back to file
-    if content_changed {
-        let mut file = File::create(&state.filename).await?;
-        let cleaned_content = content
-            .iter()
-            .map(|line| line.replace('\n', "\\n"))
-            .collect::<Vec<String>>()
-            .join("\n");
-        file.write_all(cleaned_content.as_bytes()).await?;
+    // Write updated content back to file, or under `--output-dir`
+    let target = output.unwrap_or_else(|| PathBuf::from(&state.filename));
+    let start = Instant::now();
+    if let Some(new_content) = &new_content {
+        if !config.check {
+            write_file(&target, new_content).await?;
+
+            if config.verbose {
+                emit!("Fixed `{}`", target.display());
+            }
+        }
+    } else if config.copy_unchanged && !config.check {
+        write_file(&target, &state.content).await?;
     }
+    timings.write = start.elapsed();
 
-    Ok(content_changed)
+    Ok((changes, stats, timings))
 }
 
-/// Parse the program and find all the changes that need to be made
-pub fn get_changes(content: &str, filename: &str) -> Vec<Change> {
-    let mut visitor = LoggerVisitor { changes: vec![] };
+/// Apply changes to file content, returning the new content if anything changed.
+///
+/// With `--validate`, the new content is parsed again, and content that no longer
+/// parses is an error rather than something to write.
+fn fixed_content(
+    content: &str,
+    filename: &str,
+    changes: &[Change],
+    config: &Config,
+) -> Result<Option<String>> {
+    let (new_content, content_changed) = change_content(content, changes, config);
+    if !content_changed {
+        return Ok(None);
+    }
+    let new_content = join_lines(&new_content);
+    if config.validate && parse_program(&new_content, filename).is_err() {
+        bail!(error_message(
+            &format!("Refusing to write `{filename}`, since the fixed content no longer parses"),
+            &config.issue_url
+        ));
+    }
+    Ok(Some(new_content))
+}
 
-    if let Ok(program) = parse_program(content, filename) {
-        program
-            .iter()
-            .for_each(|stmt| walk_stmt(&mut visitor, stmt));
-    } else {
-        // If we're unable to parse a file, we just return no changes
-        eprintln!("Failed to parse `{filename}`");
+async fn write_file(path: &Path, content: &str) -> Result<()> {
+    let write = async {
+        // Directories under `--output-dir` might not exist yet
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+        let mut file = File::create(path).await?;
+        file.write_all(content.as_bytes()).await?;
+        // tokio buffers writes, and we exit right after processing the last file
+        file.flush().await
+    };
+    write
+        .await
+        .with_context(|| format!("Failed to write `{}`", path.display()))
+}
+
+/// Find all the changes that need to be made to the loggers in a file, without applying them
+pub fn get_changes(content: &str, filename: &str, config: &Config) -> Vec<Change> {
+    get_changes_with_stats(content, filename, config).0
+}
+
+/// Find all the changes that need to be made to the loggers in a file, along with
+/// how many loggers were converted and skipped at each level
+pub fn get_changes_with_stats(
+    content: &str,
+    filename: &str,
+    config: &Config,
+) -> (Vec<Change>, Stats) {
+    let state = ThreadLocal::new(filename.to_string(), content.to_string());
+    THREAD_LOCAL_STATE.sync_scope(state, || {
+        find_changes(content, filename, config).unwrap_or_default()
+    })
+}
+
+/// Fix all loggers in a snippet of source code, without touching the filesystem.
+///
+/// Returns the new content, and whether anything changed.
+/// Content that can't be parsed is returned as-is.
+pub fn fix_string(content: &str, filename: &str, config: &Config) -> (String, bool) {
+    let state = ThreadLocal::new(filename.to_string(), content.to_string());
+    THREAD_LOCAL_STATE.sync_scope(state, || {
+        let (changes, _) = find_changes(content, filename, config).unwrap_or_default();
+        match change_content(content, &changes, config) {
+            (new_content, true) => (join_lines(&new_content), true),
+            (_, false) => (content.to_string(), false),
+        }
+    })
+}
+
+/// Fix all loggers in a snippet of source code, like `fix_string`, but failing where
+/// fixing a file would: when the content can't be parsed with `--error-on-parse-failure`,
+/// or no longer parses once fixed with `--validate`.
+pub fn try_fix_string(content: &str, filename: &str, config: &Config) -> Result<(String, bool)> {
+    let state = ThreadLocal::new(filename.to_string(), content.to_string());
+    THREAD_LOCAL_STATE.sync_scope(state, || {
+        let changes = match find_changes(content, filename, config) {
+            Some((changes, _)) => changes,
+            None if config.error_on_parse_failure => {
+                return Err(ParseFailure {
+                    filename: filename.to_string(),
+                }
+                .into())
+            }
+            None => vec![],
+        };
+        match fixed_content(content, filename, &changes, config)? {
+            Some(new_content) => Ok((new_content, true)),
+            None => Ok((content.to_string(), false)),
+        }
+    })
+}
+
+/// Fix a batch of `(filename, content)` pairs in parallel, without touching the filesystem.
+///
+/// Returns `(filename, new content, changed)` for each input, in the same order.
+pub fn fix_many(files: &[(String, String)], config: &Config) -> Vec<(String, String, bool)> {
+    files
+        .par_iter()
+        .map(|(filename, content)| {
+            let (new_content, changed) = fix_string(content, filename, config);
+            (filename.clone(), new_content, changed)
+        })
+        .collect()
+}
+
+/// Parse the program and find all the changes that need to be made,
+/// counting the loggers converted and skipped along the way.
+///
+/// Returns `None` if the file can't be parsed. Files opting out with a marker
+/// comment aren't parsed at all.
+fn find_changes(content: &str, filename: &str, config: &Config) -> Option<(Vec<Change>, Stats)> {
+    find_changes_with_timings(content, filename, config, &mut Timings::default())
+}
+
+/// Like `find_changes`, adding the time spent parsing and detecting changes, for `--profile`
+fn find_changes_with_timings(
+    content: &str,
+    filename: &str,
+    config: &Config,
+    timings: &mut Timings,
+) -> Option<(Vec<Change>, Stats)> {
+    if is_file_skipped(content) {
+        return Some((vec![], Stats::default()));
     }
 
-    visitor.changes
+    let start = Instant::now();
+    let parsed = parse_program(content, filename);
+    timings.parse += start.elapsed();
+    let program = match parsed {
+        Ok(program) => program,
+        // If we're unable to parse a file, there's nothing to change
+        Err(_) => {
+            if !config.quiet || config.error_on_parse_failure {
+                emit!("Failed to parse `{filename}`");
+                if reuses_fstring_quotes(content) {
+                    emit!("f-strings reusing their quotes inside placeholders, from Python 3.12, aren't supported yet");
+                }
+            }
+            return None;
+        }
+    };
+
+    let start = Instant::now();
+    let mut visitor = LoggerVisitor {
+        changes: vec![],
+        config,
+        skipped_rows: skipped_rows(content),
+        logging_names: logging_module_names(&program),
+        logger_aliases: logger_method_aliases(&program),
+        stats: Stats::default(),
+    };
+    program.iter().for_each(|stmt| visitor.visit_stmt(stmt));
+    timings.detect += start.elapsed();
+
+    Some((visitor.changes, visitor.stats))
+}
+
+/// Join changed lines back into file content.
+/// Newlines inside a line come from escapes in rewritten strings, and are escaped again.
+fn join_lines(content: &[String]) -> String {
+    content
+        .iter()
+        .map(|line| line.replace('\n', "\\n"))
+        .collect::<Vec<String>>()
+        .join("\n")
 }
 
 /// Mutate file content, according to changes found
-fn change_content(content: &str, changes: Vec<Change>) -> (Vec<String>, bool) {
+fn change_content(content: &str, changes: &[Change], opts: &Opts) -> (Vec<String>, bool) {
     let mut vec_content = content.split('\n').map(str::to_owned).collect::<Vec<_>>();
-    let mut popped_rows = 0;
-
-    for change in &changes {
-        let mut new_logger = format!(
-            "{}{}{}, {}",
-            change.quote,
-            change.new_string_content,
-            change.quote,
-            change.new_string_variables.join(", ")
-        );
+    let indent_unit = indent_unit(content, opts);
+
+    // Changes are applied from the end of the file back, so the ones still to come are
+    // where the parser found them, whatever the changes after them did to their lines.
+    // The order in which they're found isn't always document order, like for an assert's
+    // message and test.
+    let mut ordered = changes.iter().collect::<Vec<_>>();
+    ordered.sort_by_key(|change| (change.lineno, change.col_offset));
+
+    for change in ordered.into_iter().rev() {
+        let row = change.lineno - 1;
+        let message = change.message();
+        let mut new_logger = change.new_text();
+
+        // The parser reports columns in characters, but strings are sliced by byte
+        let mut col_offset = byte_offset(&vec_content[row], change.col_offset);
 
-        // If the logger starts and end on the same line, then we can just replace the old line with the new one
-        if change.lineno == change.end_lineno {
-            vec_content[change.lineno - 1 - popped_rows]
-                .replace_range(&change.col_offset..&change.end_col_offset, &new_logger);
+        // The message is replaced on its own, from its start to its end, which can be lines
+        // apart. Lines after the first are removed, and whatever followed the message on its
+        // last line, like a trailing comma, other arguments or the closing parenthesis, is
+        // kept after the new message. Arguments on the lines after that are left as they are.
+        let end_row = change.end_lineno - 1;
+        let removed_lines = vec_content.drain(row + 1..=end_row).collect::<Vec<_>>();
+        let last_line = removed_lines
+            .last()
+            .unwrap_or(&vec_content[row])
+            .to_string();
+        let end_col_offset = byte_offset(&last_line, change.end_col_offset);
+
+        // Comments between the lines of the message, like after each part of an
+        // implicitly concatenated string, would be removed with their lines
+        let message_source = if removed_lines.is_empty() {
+            vec_content[row][col_offset..end_col_offset].to_string()
         } else {
-            let range = change.lineno - popped_rows..change.end_lineno - popped_rows;
+            let mut message_lines = vec![&vec_content[row][col_offset..]];
+            message_lines.extend(removed_lines.iter().rev().skip(1).rev().map(String::as_str));
+            message_lines.push(&last_line[..end_col_offset]);
+            message_lines.join("\n")
+        };
+        let comments = find_comments(&message_source)
+            .into_iter()
+            .map(|(_, comment)| comment)
+            .collect::<Vec<_>>();
+        let mut suffix = append_comments(&last_line[end_col_offset..], &comments);
+        if opts.annotate_conversions {
+            suffix = append_audit_comment(&suffix, &change.original);
+        }
+        new_logger.push_str(&suffix);
+        vec_content[row].replace_range(col_offset.., &new_logger);
 
-            // Replace excess lines - we'll add the new logger on the first line
-            let removed_lines = vec_content.drain(range);
+        // Swap out the called function, e.g., `print` for `logger.info`. It can be on a line
+        // before the message, which is still as it was, since changes are made bottom-up.
+        if let Some((lineno, start, end, name)) = &change.new_call_target {
+            let target_row = lineno - 1;
+            let start = byte_offset(&vec_content[target_row], *start);
+            let end = byte_offset(&vec_content[target_row], *end);
+            vec_content[target_row].replace_range(start..end, name);
+            if target_row == row {
+                col_offset = col_offset + name.len() - (end - start);
+            }
+        }
 
-            // Add trailing comma if needed
-            if let Some(last_item) = removed_lines.last() {
-                if last_item.ends_with(',') {
-                    new_logger.push(',');
+        // Spread the logger over several lines if it no longer fits. Assert messages and
+        // `extra` values keep their arguments in the expression, so they're left as they are.
+        // Loggers before it on the line haven't been rewritten yet, so they count as they were.
+        let reflowable = !change.str_format && !change.percent_format;
+        if let Some(line_length) = opts.line_length.filter(|_| reflowable) {
+            if line_width(&vec_content[row]) > line_length {
+                let prefix = &vec_content[row][..col_offset];
+                let variables = &change.new_string_variables;
+                if let Some(lines) = reflow(prefix, &message, variables, &suffix, &indent_unit) {
+                    vec_content.splice(row..=row, lines);
                 }
             }
+        }
+    }
+
+    (vec_content, !changes.is_empty())
+}
 
-            // Write new logger to file
-            vec_content[change.lineno - 1 - popped_rows]
-                .replace_range(&change.col_offset.., &new_logger);
+/// The indentation added in front of arguments when a logger is spread over several lines.
+///
+/// Unless set with `--use-tabs` or `--indent-width`, this is the indentation of the
+/// first indented line in the file.
+fn indent_unit(content: &str, opts: &Opts) -> String {
+    if opts.use_tabs {
+        return "\t".to_string();
+    }
+    if let Some(width) = opts.indent_width {
+        return " ".repeat(width);
+    }
+    content
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            line.chars()
+                .take_while(|c| c.is_whitespace())
+                .collect::<String>()
+        })
+        .find(|indent| !indent.is_empty())
+        .unwrap_or_else(|| REFLOW_INDENT.to_string())
+}
+
+/// Find the comments in a piece of Python source, as their byte offsets and text.
+/// A `#` inside a string literal doesn't start a comment.
+pub(crate) fn find_comments(source: &str) -> Vec<(usize, &str)> {
+    let mut comments = vec![];
+    // The quotes of the string literal we're in, if any
+    let mut string_quotes: Option<&str> = None;
+    let mut offset = 0;
 
-            popped_rows += change.end_lineno - change.lineno;
+    while offset < source.len() {
+        let rest = &source[offset..];
+        let c = rest.chars().next().unwrap();
+        match string_quotes {
+            Some(_) if c == '\\' => {
+                // Skip the escaped character
+                offset += c.len_utf8();
+                offset += rest[1..].chars().next().map_or(0, char::len_utf8);
+                continue;
+            }
+            Some(quotes) if rest.starts_with(quotes) => {
+                string_quotes = None;
+                offset += quotes.len();
+                continue;
+            }
+            // Single-quoted strings can't span lines, so an unterminated one ends here
+            Some(quotes) if c == '\n' && quotes.len() == 1 => string_quotes = None,
+            Some(_) => (),
+            None if c == '#' => {
+                let end = rest.find('\n').map_or(source.len(), |end| offset + end);
+                comments.push((offset, source[offset..end].trim_end()));
+                offset = end;
+                continue;
+            }
+            None if c == '\'' || c == '"' => {
+                let quotes = if rest.starts_with("'''") || rest.starts_with("\"\"\"") {
+                    &rest[..3]
+                } else {
+                    &rest[..1]
+                };
+                string_quotes = Some(quotes);
+                offset += quotes.len();
+                continue;
+            }
+            None => (),
         }
+        offset += c.len_utf8();
     }
 
-    (vec_content, !changes.is_empty())
+    comments
+}
+
+/// Whether an f-string in a piece of Python source reuses its quotes inside a placeholder,
+/// like `f"{d["key"]}"`, which Python 3.12 allows but the parser doesn't support. Such a
+/// string seems to end before its placeholder is closed.
+fn reuses_fstring_quotes(source: &str) -> bool {
+    let mut offset = 0;
+    while offset < source.len() {
+        let rest = &source[offset..];
+        let c = rest.chars().next().unwrap();
+        if c == '#' {
+            offset += rest.find('\n').unwrap_or(rest.len());
+            continue;
+        }
+        if c != '\'' && c != '"' {
+            offset += c.len_utf8();
+            continue;
+        }
+
+        let quotes = if rest.starts_with("'''") || rest.starts_with("\"\"\"") {
+            &rest[..3]
+        } else {
+            &rest[..1]
+        };
+        let prefix = source[..offset]
+            .chars()
+            .rev()
+            .take_while(|c| c.is_alphanumeric() || *c == '_')
+            .collect::<String>();
+        let fstring = prefix.len() <= 2 && prefix.contains(['f', 'F']);
+        offset += quotes.len();
+
+        // Find where the string ends, counting the placeholders left open
+        let mut open = 0;
+        while offset < source.len() {
+            let rest = &source[offset..];
+            let c = rest.chars().next().unwrap();
+            if rest.starts_with(quotes) || (c == '\n' && quotes.len() == 1) {
+                break;
+            }
+            match c {
+                '\\' => offset += rest[1..].chars().next().map_or(0, char::len_utf8),
+                '{' if fstring && open == 0 && rest.starts_with("{{") => offset += 1,
+                '{' if fstring => open += 1,
+                '}' if fstring && open > 0 => open -= 1,
+                // A string inside a placeholder, using the other quotes
+                '\'' | '"' if open > 0 => {
+                    offset += rest[1..].find(c).map_or(rest.len() - 1, |end| end + 1);
+                }
+                _ => (),
+            }
+            offset += c.len_utf8();
+        }
+        if open > 0 && source[offset..].starts_with(quotes) {
+            return true;
+        }
+        offset += quotes.len();
+    }
+    false
+}
+
+/// Add comments to what follows a rewritten message on its line,
+/// ahead of any comment that's already there
+fn append_comments(suffix: &str, comments: &[&str]) -> String {
+    if comments.is_empty() {
+        return suffix.to_string();
+    }
+    let (code, existing_comment) = match find_comments(suffix).first() {
+        Some((offset, comment)) => (&suffix[..*offset], Some(*comment)),
+        None => (suffix, None),
+    };
+    let mut suffix = format!("{}  {}", code.trim_end(), comments.join("  "));
+    if let Some(comment) = existing_comment {
+        suffix.push_str("  ");
+        suffix.push_str(comment);
+    }
+    suffix
+}
+
+/// Add a `# was: ...` comment with the original message to what follows a rewritten message on
+/// its line, for `--annotate-conversions`. It goes after any comment that's already there, so
+/// ones that tools look for at the start of a comment, like `# type: ignore`, keep working.
+///
+/// Messages spanning several lines are put on one, without their comments. Lines continued
+/// with a backslash can't end with a comment, so they're left as they are.
+fn append_audit_comment(suffix: &str, original: &str) -> String {
+    if suffix.trim_end().ends_with('\\') {
+        return suffix.to_string();
+    }
+    let mut original_code = String::new();
+    let mut last_end = 0;
+    for (offset, comment) in find_comments(original) {
+        original_code.push_str(&original[last_end..offset]);
+        last_end = offset + comment.len();
+    }
+    original_code.push_str(&original[last_end..]);
+    let original = original_code
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    // Later changes on the line are made first, so their comments go after this one
+    let code_end = find_comments(suffix)
+        .first()
+        .map_or(suffix.len(), |(offset, _)| *offset);
+    let (before, after) = match suffix[code_end..].find("# was: ") {
+        Some(offset) => suffix.split_at(code_end + offset),
+        None => (suffix, ""),
+    };
+    let mut suffix = format!("{}  # was: {original}", before.trim_end());
+    if !after.is_empty() {
+        suffix.push_str("  ");
+        suffix.push_str(after);
+    }
+    suffix
+}
+
+/// Convert a column counted in characters to a byte offset into the line
+pub(crate) fn byte_offset(line: &str, column: usize) -> usize {
+    line.char_indices()
+        .nth(column)
+        .map_or(line.len(), |(offset, _)| offset)
+}
+
+/// The number of characters a line will take up once written to file.
+/// Newlines inside the line are written as `\n`, so they count twice.
+fn line_width(line: &str) -> usize {
+    line.chars().count() + line.matches('\n').count()
+}
+
+/// Lay out a rewritten logger with the message and each argument on their own line, like
+///
+/// ```python
+/// logger.error(
+///     "%s and %s",
+///     foo,
+///     bar,
+///     exc_info=True,
+/// )
+/// ```
+///
+/// Arguments following the message on its line are split the same way, like black does.
+/// Those continuing on the lines after it are left where they are.
+/// Returns `None` when the message isn't the first argument of the call, since
+/// there's no obvious way to split those.
+fn reflow(
+    prefix: &str,
+    message: &str,
+    variables: &[String],
+    suffix: &str,
+    indent_unit: &str,
+) -> Option<Vec<String>> {
+    let indent = prefix
+        .chars()
+        .take_while(|c| c.is_whitespace())
+        .collect::<String>();
+
+    let mut lines = vec![];
+    let inner_indent = if prefix.trim().is_empty() {
+        // The message is already on its own line
+        indent.clone()
+    } else if prefix.trim_end().ends_with('(') {
+        lines.push(prefix.trim_end().to_string());
+        format!("{indent}{indent_unit}")
+    } else {
+        return None;
+    };
+
+    let mut items = vec![message.to_string()];
+    items.extend(variables.iter().cloned());
+    let last = items.pop().unwrap();
+
+    for item in items {
+        lines.push(format!("{inner_indent}{item},"));
+    }
+
+    // Close the call on its own line, after any arguments that followed on the message's line.
+    // When the call continues on the next lines, what's left of this one ends the last item.
+    if let Some((arguments, rest)) = split_trailing_arguments(suffix) {
+        lines.push(format!("{inner_indent}{last},"));
+        for argument in arguments {
+            lines.push(format!("{inner_indent}{argument},"));
+        }
+        lines.push(format!("{indent}{rest}"));
+    } else if suffix.is_empty() {
+        lines.push(format!("{inner_indent}{last},"));
+    } else {
+        lines.push(format!("{inner_indent}{last}{suffix}"));
+    }
+
+    Some(lines)
+}
+
+/// Split what follows a message into the arguments after it and the rest of the line, starting
+/// from the parenthesis closing the call, like `["x", "exc_info=True"]` and `) or y` for
+/// `, x, exc_info=True) or y`.
+///
+/// Returns `None` when the call isn't closed on the line, or a comment comes before that.
+fn split_trailing_arguments(suffix: &str) -> Option<(Vec<&str>, &str)> {
+    let suffix = suffix.trim_start();
+    let arguments_source = suffix.strip_prefix(',').unwrap_or(suffix);
+    if !arguments_source.trim_start().starts_with(')') && arguments_source.len() == suffix.len() {
+        return None;
+    }
+    let offset = suffix.len() - arguments_source.len();
+
+    let mut arguments = vec![];
+    let mut depth = 0;
+    // The quote of the string literal we're in, if any
+    let mut quote: Option<char> = None;
+    let mut escaped = false;
+    let mut argument_start = 0;
+    for (i, c) in arguments_source.char_indices() {
+        match quote {
+            Some(_) if escaped => escaped = false,
+            Some(_) if c == '\\' => escaped = true,
+            Some(q) if c == q => quote = None,
+            Some(_) => (),
+            None => match c {
+                '\'' | '"' => quote = Some(c),
+                '(' | '[' | '{' => depth += 1,
+                ')' if depth == 0 => {
+                    arguments.push(arguments_source[argument_start..i].trim());
+                    arguments.retain(|argument| !argument.is_empty());
+                    return Some((arguments, &suffix[offset + i..]));
+                }
+                ')' | ']' | '}' => depth -= 1,
+                ',' if depth == 0 => {
+                    arguments.push(arguments_source[argument_start..i].trim());
+                    argument_start = i + 1;
+                }
+                '#' => return None,
+                _ => (),
+            },
+        }
+    }
+    None
 }
 
 #[cfg(test)]
 mod tests {
     use assert_panic::assert_panic;
+    use clap::Parser;
+    use rustpython_parser::ast::{Constant, Expr, ExprKind, Stmt, StmtKind};
 
-    use crate::cli::{LogLevel, Opts};
-    use crate::{ThreadLocal, SETTINGS};
+    use crate::cli::{error_message, LogLevel, LoggerFlavor, QuoteStyle, DEFAULT_ISSUE_URL};
+    use crate::{Position, TextEdit};
 
     use super::*;
 
@@ -103,17 +664,25 @@ mod tests {
         expected_output: String,
     }
 
+    /// Default settings, as if the binary was called with just a filename
+    fn opts() -> Opts {
+        Opts::parse_from(["printf-log-formatter", "test.py"])
+    }
+
     async fn run(test_case: TestCase) {
+        run_with_opts(test_case, &opts()).await;
+    }
+
+    async fn run_with_opts(test_case: TestCase, opts: &Opts) {
         let (content, _changed) = THREAD_LOCAL_STATE
             .scope(
-                ThreadLocal {
-                    filename: "test.py".to_string(),
-                    content: test_case.input,
-                },
+                ThreadLocal::new("test.py".to_string(), test_case.input),
                 async move {
                     let state = THREAD_LOCAL_STATE.with(Clone::clone);
-                    let changes = get_changes(&state.content, &state.filename);
-                    change_content(&state.content, changes)
+                    let config = Config::from_opts(opts);
+                    let (changes, _) =
+                        find_changes(&state.content, &state.filename, &config).unwrap();
+                    change_content(&state.content, &changes, opts)
                 },
             )
             .await;
@@ -127,11 +696,22 @@ mod tests {
             // Simple
             TestCase { input: "logger.error('{}'.format(1))".to_string(), expected_output: "logger.error('%s', 1)".to_string() },
             // With formatting
-            TestCase { input: "logger.error('{:02f}'.format(1))".to_string(), expected_output: "logger.error('%s', 1)".to_string() },
+            TestCase { input: "logger.error('{:02f}'.format(1))".to_string(), expected_output: "logger.error('%02f', 1)".to_string() },
+            TestCase { input: "logger.error('{:.2f} {:x}'.format(1.5, 255))".to_string(), expected_output: "logger.error('%.2f %x', 1.5, 255)".to_string() },
+            // Conversions and alignment
+            TestCase { input: "logger.error('{!r} {:>10}'.format(a, b))".to_string(), expected_output: "logger.error('%r %10s', a, b)".to_string() },
+            TestCase { input: "logger.error('{:<5.2f}|{!a:<8}|{!s}'.format(a, b, c))".to_string(), expected_output: "logger.error('%-5.2f|%-8a|%s', a, b, c)".to_string() },
+            TestCase { input: "logger.error('{foo!r} {bar:>3d}'.format(foo=1, bar=2))".to_string(), expected_output: "logger.error('%r %3d', 1, 2)".to_string() },
+            // Signs, and space fill
+            TestCase { input: "logger.error('{:+d} {: <6} {:+08.3f}'.format(a, b, c))".to_string(), expected_output: "logger.error('%+d %-6s %+08.3f', a, b, c)".to_string() },
+            TestCase { input: "logger.error(f'{a: d} {b:<+5d}')".to_string(), expected_output: "logger.error('% d %-+5d', a, b)".to_string() },
+            // Formatting without a printf-style equivalent
+            TestCase { input: "logger.error('{:^10} {:*>10} {:10} {:.2}'.format(1, 2, 3, 4))".to_string(), expected_output: "logger.error('%s %s %s %s', 1, 2, 3, 4)".to_string() },
+            TestCase { input: "logger.error(f'{a:=+5d} {b:+}')".to_string(), expected_output: "logger.error('%s %s', a, b)".to_string() },
             // Named variable
             TestCase { input: "logger.error('{foo}'.format(foo=1))".to_string(), expected_output: "logger.error('%s', 1)".to_string() },
             // With formatting
-            TestCase { input: "logger.error('{foo:02f}'.format(foo=1))".to_string(), expected_output: "logger.error('%s', 1)".to_string() },
+            TestCase { input: "logger.error('{foo:02f}'.format(foo=1))".to_string(), expected_output: "logger.error('%02f', 1)".to_string() },
             // Weird ordering
             TestCase { input: "logger.error('{x} + {} == {y}'.format(3, y=4, x=1))".to_string(), expected_output: "logger.error('%s + %s == %s', 1, 3, 4)".to_string() },
             // Packed single line
@@ -155,17 +735,46 @@ mod tests {
             TestCase { input: "logging.error('Error parsing event file: {}'.format(e.errors()))".to_string(), expected_output: "logging.error('Error parsing event file: %s', e.errors())".to_string() },
             // Index
             TestCase { input: "logger.error('{}'.format(ret[\"id\"]))".to_string(), expected_output: "logger.error('%s', ret['id'])".to_string() },
+            // Parenthesized target
+            TestCase { input: "logger.error((\"{}\").format(x))".to_string(), expected_output: "logger.error(\"%s\", x)".to_string() },
+            // Manually numbered placeholders
+            TestCase { input: "logger.error('{0} {1}'.format(a, b))".to_string(), expected_output: "logger.error('%s %s', a, b)".to_string() },
+            TestCase { input: "logger.error('{1} {0!r} {1:>3}'.format(a, b))".to_string(), expected_output: "logger.error('%s %r %3s', b, a, b)".to_string() },
+            TestCase { input: "logger.error('{x} {1} {0}'.format(a, b, x=1))".to_string(), expected_output: "logger.error('%s %s %s', 1, b, a)".to_string() },
+            TestCase { input: "logger.error('{0.name}'.format(a))".to_string(), expected_output: "logger.error('{0.name}'.format(a))".to_string() },
+            // Nested str.format() calls are spliced into the message
+            TestCase { input: "logger.error('{} {}'.format(a, '{}'.format(b)))".to_string(), expected_output: "logger.error('%s %s', a, b)".to_string() },
+            TestCase { input: "logger.error('{} ({})'.format(a, '{}/{}%'.format(b, c)))".to_string(), expected_output: "logger.error('%s (%s/%s%%)', a, b, c)".to_string() },
+            TestCase { input: "logger.error('{}'.format('<{}>'.format('{}!'.format(x))))".to_string(), expected_output: "logger.error('<%s!>', x)".to_string() },
+            TestCase { input: "logger.error('{a} {}'.format('[{}]'.format(b), a=1))".to_string(), expected_output: "logger.error('%s [%s]', 1, b)".to_string() },
+            // So are f-strings passed as arguments
+            TestCase { input: "logger.error('{}'.format(f'{x}'))".to_string(), expected_output: "logger.error('%s', x)".to_string() },
+            TestCase { input: "logger.error('a {} b {}'.format(f'<{x!r}-{y:>3}> 100%', z))".to_string(), expected_output: "logger.error('a <%r-%3s> 100%% b %s', x, y, z)".to_string() },
+            TestCase { input: "logger.error('{}'.format(f'{x} ' f'{y}'))".to_string(), expected_output: "logger.error('%s %s', x, y)".to_string() },
+            // ...unless the placeholder or the nested call need more than that
+            TestCase { input: "logger.error('{:>5}'.format('{}'.format(b)))".to_string(), expected_output: "logger.error('%5s', '{}'.format(b))".to_string() },
+            TestCase { input: "logger.error('{}'.format('{n}'.format(n=b)))".to_string(), expected_output: "logger.error('%s', '{n}'.format(n=b))".to_string() },
+            TestCase { input: "logger.error('{:>5}'.format(f'{x}'))".to_string(), expected_output: "logger.error('{:>5}'.format(f'{x}'))".to_string() },
+            TestCase { input: "logger.error('{}'.format(f'{{x}} {y:{w}}'))".to_string(), expected_output: "logger.error('{}'.format(f'{{x}} {y:{w}}'))".to_string() },
+            // Methods called on the formatted message change it, so the logger is left alone
+            TestCase { input: "logger.error('{}'.format(x).upper())".to_string(), expected_output: "logger.error('{}'.format(x).upper())".to_string() },
+            // Implicitly concatenated target
+            TestCase { input: "logger.error(\n\t('foo {} '\n\t'bar {}').format(x, y)\n)".to_string(), expected_output: "logger.error(\n\t'foo %s bar %s', x, y\n)".to_string() },
+            TestCase { input: "logger.error(\n\t('line one {}\\n'\n\t'line two {}').format(a, b)\n)".to_string(), expected_output: "logger.error(\n\t'line one %s\nline two %s', a, b\n)".to_string() },
+            TestCase { input: "logger.error(\n\t('{} '\n\t'{x} '\n\t'{}').format(a, b, x=1)\n)".to_string(), expected_output: "logger.error(\n\t'%s %s %s', a, 1, b\n)".to_string() },
+            TestCase { input: "logger.error('{}, ' '{}'.format(a, b))".to_string(), expected_output: "logger.error('%s, %s', a, b)".to_string() },
         ]
     }
 
     #[tokio::test]
     async fn test_change_content_format() {
-        SETTINGS.get_or_init(|| Opts {
-            log_level: LogLevel::Error,
-            filenames: vec![],
-        });
+        // Some cases call `logging.error()` directly
+        let opts = Opts {
+            include_module_logging: true,
+            ..opts()
+        };
         for test_case in format_test_cases() {
-            run(test_case).await;
+            run_with_opts(test_case, &opts).await;
         }
     }
 
@@ -175,9 +784,26 @@ mod tests {
             // Simple
             TestCase { input: "logger.error(f'{1}')".to_string(), expected_output: "logger.error('%s', 1)".to_string() },
             // With formatting
-            TestCase { input: "logger.error(f'{1:02f}')".to_string(), expected_output: "logger.error('%s', 1)".to_string() },
+            TestCase { input: "logger.error(f'{1:02f}')".to_string(), expected_output: "logger.error('%02f', 1)".to_string() },
+            TestCase { input: "logger.error(f'{x:.2f} {y:>10} {z:^10}')".to_string(), expected_output: "logger.error('%.2f %10s %s', x, y, z)".to_string() },
+            // Conversions
+            TestCase { input: "logger.error(f'{x!r} {y!s} {z!a}')".to_string(), expected_output: "logger.error('%r %s %a', x, y, z)".to_string() },
+            // Alternating literal text and placeholders
+            TestCase { input: "logger.error(f'a{b}c{d}e')".to_string(), expected_output: "logger.error('a%sc%se', b, d)".to_string() },
+            TestCase { input: "logger.error(f'{a}b{c!r}d{e:>3}f{g}')".to_string(), expected_output: "logger.error('%sb%rd%3sf%s', a, c, e, g)".to_string() },
+            TestCase { input: "logger.error(f'a{b}' 'c' f'{d}e' 'f')".to_string(), expected_output: "logger.error('a%sc%sef', b, d)".to_string() },
+            // Self-documenting expressions
+            TestCase { input: "logger.error(f'{x=}')".to_string(), expected_output: "logger.error('x=%r', x)".to_string() },
+            TestCase { input: "logger.error(f'{x=!r}')".to_string(), expected_output: "logger.error('x=%r', x)".to_string() },
+            TestCase { input: "logger.error(f'{x=:.2f}')".to_string(), expected_output: "logger.error('x=%.2f', x)".to_string() },
             // Variable
             TestCase { input: "logger.error(f'{foo}')".to_string(), expected_output: "logger.error('%s', foo)".to_string() },
+            // Only the message is converted; a later f-string is just a value
+            TestCase { input: "logger.error(f'{a}', f'{b}')".to_string(), expected_output: "logger.error('%s', a, f'{b}')".to_string() },
+            // Constants
+            TestCase { input: "logger.error(f'{\"literal\"}')".to_string(), expected_output: "logger.error('%s', 'literal')".to_string() },
+            TestCase { input: "logger.error(f'{None}')".to_string(), expected_output: "logger.error('%s', None)".to_string() },
+            TestCase { input: "logger.error(f'{True}')".to_string(), expected_output: "logger.error('%s', True)".to_string() },
             // Packed single line
             TestCase { input: "logger.error(f'{1}') or 1 + 1 == 3".to_string(), expected_output: "logger.error('%s', 1) or 1 + 1 == 3".to_string() },
             // Log level below default - expect no change
@@ -185,8 +811,16 @@ mod tests {
             TestCase { input: "logger.info(f'{1}')".to_string(), expected_output: "logger.info(f'{1}')".to_string() },
             TestCase { input: "logger.warning(f'{1}')".to_string(), expected_output: "logger.warning(f'{1}')".to_string() },
             TestCase { input: "logger.warn(f'{1}')".to_string(), expected_output: "logger.warn(f'{1}')".to_string() },
+            // Literal braces are collapsed, since printf-style messages don't need them escaped
+            TestCase { input: "logger.error(f'config {{ {value} }}')".to_string(), expected_output: "logger.error('config { %s }', value)".to_string() },
+            TestCase { input: "logger.error(f'{{}} {{{value}}}')".to_string(), expected_output: "logger.error('{} {%s}', value)".to_string() },
             // Nested properties
             TestCase { input: "logger.error(f'{a.b.c.d}')".to_string(), expected_output: "logger.error('%s', a.b.c.d)".to_string() },
+            // Attribute chains starting with a call or an index
+            TestCase { input: "logger.error(f'{func().a.b}')".to_string(), expected_output: "logger.error('%s', func().a.b)".to_string() },
+            TestCase { input: "logger.error(f'{func().a.method()}')".to_string(), expected_output: "logger.error('%s', func().a.method())".to_string() },
+            TestCase { input: "logger.error(f'{obj.get(1).a}')".to_string(), expected_output: "logger.error('%s', obj.get(1).a)".to_string() },
+            TestCase { input: "logger.error(f'{a[0].b}')".to_string(), expected_output: "logger.error('%s', a[0].b)".to_string() },
             // Call in string
             TestCase { input: "logger.error(f'foo {len(bar)}')".to_string(), expected_output: "logger.error('foo %s', len(bar))".to_string() },
             // Binary operation
@@ -203,41 +837,117 @@ mod tests {
             TestCase { input: "logger.error(f'{[str(e) for errors in all_errors for e in errors]}')".to_string(), expected_output: "logger.error('%s', [str(e) for errors in all_errors for e in errors])".to_string() },
             // Dict comprehension
             TestCase { input: "logger.error(f'{ {\"foo\": str(e) for errors in all_errors for e in errors} }')".to_string(), expected_output: "logger.error('%s', {'foo': str(e) for errors in all_errors for e in errors})".to_string() },
+            // Dict
+            TestCase { input: "logger.error(f'{ {\"a\": 1, \"b\": c} }')".to_string(), expected_output: "logger.error('%s', {'a': 1, 'b': c})".to_string() },
+            // F-strings nested in a placeholder are spliced into the message
+            TestCase { input: "logger.error(f'a {f\"{y} b, 100%\"} c')".to_string(), expected_output: "logger.error('a %s b, 100%% c', y)".to_string() },
+            TestCase { input: "logger.error(f'{f\"<{x!r}>\"} {z}')".to_string(), expected_output: "logger.error('<%r> %s', x, z)".to_string() },
+            // ...unless they're converted, formatted or have no placeholders of their own
+            TestCase { input: "logger.error(f'a {f\"{y}\"!r} c')".to_string(), expected_output: "logger.error(f'a {f\"{y}\"!r} c')".to_string() },
+            TestCase { input: "logger.error(f'a {f\"{y}\":>5} c')".to_string(), expected_output: "logger.error(f'a {f\"{y}\":>5} c')".to_string() },
+            TestCase { input: "logger.error(f'a {f\"b\"} c {d}')".to_string(), expected_output: "logger.error(f'a {f\"b\"} c {d}')".to_string() },
+            // Nested dicts and sets
+            TestCase { input: "logger.error(f\"{ {'a': {'b': c}} }\")".to_string(), expected_output: "logger.error(\"%s\", {\"a\": {\"b\": c}})".to_string() },
+            TestCase { input: "logger.error(f'{ {\"a\": {b, 1}} }')".to_string(), expected_output: "logger.error('%s', {'a': {b, 1}})".to_string() },
+            TestCase { input: "logger.error(f'{ {a, (b, c)} }')".to_string(), expected_output: "logger.error('%s', {a, (b, c)})".to_string() },
+            // Dict with unpacking
+            TestCase { input: "logger.error(f'{ {\"a\": 1, **other} }')".to_string(), expected_output: "logger.error('%s', {'a': 1, **other})".to_string() },
             // Call containing list comprehension
             TestCase { input: "logger.error(f'{\", \".join([str(e) for e in errors for errors in all_errors])}')".to_string(), expected_output: "logger.error('%s', ', '.join([str(e) for e in errors for errors in all_errors]))".to_string() },
             // Generator
-            TestCase { input: "logger.exception(f'{\", \".join(b for b in bs)}')".to_string(), expected_output: "logger.exception('%s', ', '.join([b for b in bs]))".to_string() },
+            TestCase { input: "logger.exception(f'{\", \".join(b for b in bs)}')".to_string(), expected_output: "logger.exception('%s', ', '.join(b for b in bs))".to_string() },
+            TestCase { input: "logger.exception(f'{\", \".join(str(b) for b in bs)} {sum(x for x in xs)}')".to_string(), expected_output: "logger.exception('%s %s', ', '.join(str(b) for b in bs), sum([x for x in xs]))".to_string() },
             // Named args in calls
             TestCase { input: "logger.error(f'{something(1, x=2, y=4)}')".to_string(), expected_output: "logger.error('%s', something(1, x=2, y=4))".to_string() },
+            TestCase { input: "logger.error(f'{something(a, b=c, d=e.f, g=h[0])}')".to_string(), expected_output: "logger.error('%s', something(a, b=c, d=e.f, g=h[0]))".to_string() },
+            TestCase { input: "logger.error(f'{something(*args, key=\"v\", **kwargs)}')".to_string(), expected_output: "logger.error('%s', something(*args, key='v', **kwargs))".to_string() },
+            TestCase { input: "logger.error(f'{func(a, *args, *[1, \"b\"], k=1, **kwargs, **{\"x\": y})}')".to_string(), expected_output: "logger.error('%s', func(a, *args, *[1, 'b'], k=1, **kwargs, **{'x': y}))".to_string() },
+            TestCase { input: "logger.error(f'{obj.method(*a.b, *f(x), **g()).attr}')".to_string(), expected_output: "logger.error('%s', obj.method(*a.b, *f(x), **g()).attr)".to_string() },
+            TestCase { input: "logger.error(f'{[*a, *b][0]} {[]}')".to_string(), expected_output: "logger.error('%s %s', [*a, *b][0], [])".to_string() },
+            // Calls with keyword arguments in a chain of subscripts
+            TestCase { input: "logger.error(f\"{client.get(url, timeout=5)['data'][0]}\")".to_string(), expected_output: "logger.error(\"%s\", client.get(url, timeout=5)[\"data\"][0])".to_string() },
+            TestCase { input: "logger.error(f'{client.get(url, timeout=t)[\"data\"][0].json(strict=False).items}')".to_string(), expected_output: "logger.error('%s', client.get(url, timeout=t)['data'][0].json(strict=False).items)".to_string() },
+            // Subscripts with tuple indexes
+            TestCase { input: "logger.error(f'{arr[i, j]}')".to_string(), expected_output: "logger.error('%s', arr[i, j])".to_string() },
+            TestCase { input: "logger.error(f'{arr[i, j, 0]} and {grid[x.y, 1]}')".to_string(), expected_output: "logger.error('%s and %s', arr[i, j, 0], grid[x.y, 1])".to_string() },
+            // Tuples
+            TestCase { input: "logger.error(f'{(a, 1)} {(b,)}')".to_string(), expected_output: "logger.error('%s %s', (a, 1), (b,))".to_string() },
+            // Negative and computed indexes, and slices
+            TestCase { input: "logger.error(f'{data[-1]} {data[n-1]} {data[n - 1]}')".to_string(), expected_output: "logger.error('%s %s %s', data[-1], data[n-1], data[n - 1])".to_string() },
+            TestCase { input: "logger.error(f'{data[(n - 1) * 2]} {data[-(i + 1)]}')".to_string(), expected_output: "logger.error('%s %s', data[(n - 1) * 2], data[-(i + 1)])".to_string() },
+            TestCase { input: "logger.error(f'{data[-3:]} {data[::2]} {data[a:b:-1]} {grid[1:2, ::-1]}')".to_string(), expected_output: "logger.error('%s %s %s %s', data[-3:], data[::2], data[a:b:-1], grid[1:2, ::-1])".to_string() },
+            // Unary operations, and operations keeping the parentheses they need
+            TestCase { input: "logger.error(f'{not a} {~b} {(-c)[0]} {(-d).real} {-e ** 2} {(-f) ** 2}')".to_string(), expected_output: "logger.error('%s %s %s %s %s %s', not a, ~b, (-c)[0], (-d).real, -e ** 2, (-f) ** 2)".to_string() },
+            TestCase { input: "logger.error(f'{a - (b - c)} {(a - b) - c} {a ** (b ** c)} {(a ** b) ** c} {a * (b + c)} {(a | b) & c}')".to_string(), expected_output: "logger.error('%s %s %s %s %s %s', a - (b - c), a - b - c, a ** b ** c, (a ** b) ** c, a * (b + c), (a | b) & c)".to_string() },
+            // Without placeholders, there's nothing to convert
+            TestCase { input: "logger.error(f\"just text\")".to_string(), expected_output: "logger.error(f\"just text\")".to_string() },
+            TestCase { input: "logger.error(f'just ' f'{{text}}', exc_info=True)".to_string(), expected_output: "logger.error(f'just ' f'{{text}}', exc_info=True)".to_string() },
+            TestCase { input: "logger.error(f'just' if a else f'text')".to_string(), expected_output: "logger.error(f'just' if a else f'text')".to_string() },
         ]
     }
 
     #[tokio::test]
     async fn test_change_content_fstring() {
-        SETTINGS.get_or_init(|| Opts {
-            log_level: LogLevel::Error,
-            filenames: vec![],
-        });
+        // Some cases call `logging.error()` directly
+        let opts = Opts {
+            include_module_logging: true,
+            ..opts()
+        };
         for test_case in fstring_test_cases() {
-            run(test_case).await;
+            run_with_opts(test_case, &opts).await;
+        }
+    }
+
+    #[test]
+    fn test_idempotency() {
+        // Converted output still parses, and is left as it is when fixed again
+        let loguru_opts = Opts {
+            logger_flavor: LoggerFlavor::Loguru,
+            ..opts()
+        };
+        let line_length_opts = Opts {
+            line_length: Some(40),
+            ..opts()
+        };
+        let module_logging_opts = Opts {
+            include_module_logging: true,
+            ..opts()
+        };
+        let suites = [
+            (format_test_cases(), module_logging_opts.clone()),
+            (fstring_test_cases(), module_logging_opts),
+            (regression_cases(), opts()),
+            (loguru_test_cases(), loguru_opts),
+            (line_length_test_cases(), line_length_opts),
+        ];
+        for (test_cases, opts) in suites {
+            let config = Config::from_opts(&opts);
+            for test_case in test_cases {
+                let (fixed, changed) = fix_string(&test_case.input, "test.py", &config);
+                if !changed {
+                    continue;
+                }
+                assert!(parse_program(&fixed, "test.py").is_ok(), "{fixed}");
+                assert_eq!(
+                    fix_string(&fixed, "test.py", &config),
+                    (fixed.clone(), false)
+                );
+            }
         }
     }
 
     #[test]
     fn test_change_content_format_with_too_many_arguments_panics() {
-        SETTINGS.get_or_init(|| Opts {
-            log_level: LogLevel::Error,
-            filenames: vec![],
-        });
         assert_panic!(
             tokio_test::block_on(
                 async {
                     THREAD_LOCAL_STATE.scope(
-                        ThreadLocal { filename: "test.py".to_string(), content: "logger.error('{}'.format(1,2))".to_string() },
+                        ThreadLocal::new("test.py".to_string(), "logger.error('{}'.format(1,2))".to_string()),
                         async move {
                             let state = THREAD_LOCAL_STATE.with(Clone::clone);
-                            let changes = get_changes(&state.content, &state.filename);
-                            change_content(&state.content, changes);
+                            let opts = opts();
+                            let (changes, _) = find_changes(&state.content, &state.filename, &Config::from_opts(&opts)).unwrap();
+                            change_content(&state.content, &changes, &opts);
                         }
                     ).await;
                 }
@@ -260,17 +970,1689 @@ mod tests {
             TestCase { input: "warnings.error(f'{1}')".to_string(), expected_output: "warnings.error(f'{1}')".to_string() },
             // Quotes are set correctly
             TestCase { input: "logger.error(f\"{1}\")\nlogger.error(f'{2}')".to_string(), expected_output: "logger.error(\"%s\", 1)\nlogger.error('%s', 2)".to_string() },
+            // Skip markers
+            TestCase { input: "logger.error(f'{1}')  # printf: skip\nlogger.error(f'{2}')".to_string(), expected_output: "logger.error(f'{1}')  # printf: skip\nlogger.error('%s', 2)".to_string() },
+            TestCase { input: "logger.error(f'{1}')\nlogger.error('{}'.format(2))  # noqa: E501, printf-log-formatter".to_string(), expected_output: "logger.error('%s', 1)\nlogger.error('{}'.format(2))  # noqa: E501, printf-log-formatter".to_string() },
+            TestCase { input: "logger.error(  # printf: skip\n\tf'{1}'\n)\nlogger.error(f'{2}')".to_string(), expected_output: "logger.error(  # printf: skip\n\tf'{1}'\n)\nlogger.error('%s', 2)".to_string() },
+            TestCase { input: "logger.error(f'{1} # printf: skip')".to_string(), expected_output: "logger.error('%s # printf: skip', 1)".to_string() },
+            TestCase { input: "#!/usr/bin/env python\n# printf-log-formatter: off\nlogger.error(f'{1}')".to_string(), expected_output: "#!/usr/bin/env python\n# printf-log-formatter: off\nlogger.error(f'{1}')".to_string() },
+            TestCase { input: "x = 1  # printf-log-formatter: off\nlogger.error(f'{1}')".to_string(), expected_output: "x = 1  # printf-log-formatter: off\nlogger.error('%s', 1)".to_string() },
+            TestCase { input: format!("{}# printf-log-formatter: off\nlogger.error(f'{{1}}')", "\n".repeat(10)), expected_output: format!("{}# printf-log-formatter: off\nlogger.error('%s', 1)", "\n".repeat(10)) },
+            // Escaped quotes stay escaped
+            TestCase { input: "logger.error(f'it\\'s {x}')".to_string(), expected_output: "logger.error('it\\'s %s', x)".to_string() },
+            // Blank lines after multiline loggers are kept
+            TestCase { input: "logger.error(\n\tf'{1}'\n\tf'{2}'\n)\n\n\nfoo()".to_string(), expected_output: "logger.error(\n\t'%s%s', 1, 2\n)\n\n\nfoo()".to_string() },
+            TestCase { input: "logger.error(\n\t'{}'.format(\n\t\t1\n\t)\n)\n\nlogger.error(\n\t'{}'.format(\n\t\t2\n\t)\n)\n\nfoo()".to_string(), expected_output: "logger.error(\n\t'%s', 1\n)\n\nlogger.error(\n\t'%s', 2\n)\n\nfoo()".to_string() },
+            // Tab indentation and non-ASCII characters before or after the message
+            TestCase { input: "def f():\n\tlogger.error(f'{x}', exc_info=True)".to_string(), expected_output: "def f():\n\tlogger.error('%s', x, exc_info=True)".to_string() },
+            TestCase { input: "def f():\n\tif x:\n\t\tlogger.error(\n\t\t\tf'{x}'\n\t\t\tf'{y}', exc_info=True\n\t\t)".to_string(), expected_output: "def f():\n\tif x:\n\t\tlogger.error(\n\t\t\t'%s%s', x, y, exc_info=True\n\t\t)".to_string() },
+            TestCase { input: "x = 'é'; logger.error(f'æ {x}')  # ø".to_string(), expected_output: "x = 'é'; logger.error('æ %s', x)  # ø".to_string() },
+            TestCase { input: "logger.error(\n\tf'å {x}'\n\tf'ø {y}', 'é'\n)".to_string(), expected_output: "logger.error(\n\t'å %sø %s', x, y, 'é'\n)".to_string() },
+            // Mixed f-string and plain string concatenation over several lines
+            TestCase { input: "logger.error(\n\tf'a {x} '\n\t'plain '\n\tf'{y}',\n\texc_info=True,\n)".to_string(), expected_output: "logger.error(\n\t'a %s plain %s', x, y,\n\texc_info=True,\n)".to_string() },
+            TestCase { input: "logger.error(\n\t'plain '\n\tf'a {x} '\n\t'{y}'\n)".to_string(), expected_output: "logger.error(\n\t'plain a %s {y}', x\n)".to_string() },
+            TestCase { input: "logger.error(f'a {x} '\n\t'plain '\n\tf'{y}', exc_info=True)\nfoo()".to_string(), expected_output: "logger.error('a %s plain %s', x, y, exc_info=True)\nfoo()".to_string() },
+            // Comments between the pieces used to be dropped with their lines. They're moved
+            // ahead of the message's own comment now, like in `comment_test_cases`
+            TestCase { input: "logger.error(\n\tf'a {x} '  # first\n\t'plain '\n\tf'{y}'  # last\n)".to_string(), expected_output: "logger.error(\n\t'a %s plain %s', x, y  # first  # last\n)".to_string() },
+            // Inline loggers
+            TestCase { input: "logging.getLogger(__name__).error(f'{x}')".to_string(), expected_output: "logging.getLogger(__name__).error('%s', x)".to_string() },
+            TestCase { input: "getLogger('foo').error('{}'.format(x))".to_string(), expected_output: "getLogger('foo').error('%s', x)".to_string() },
+            TestCase { input: "messages.storage(request).error(f'{x}')".to_string(), expected_output: "messages.storage(request).error(f'{x}')".to_string() },
+            // Loggers nested in lambdas, comprehensions and other calls
+            TestCase { input: "fn = lambda: logger.error(f'{x}')".to_string(), expected_output: "fn = lambda: logger.error('%s', x)".to_string() },
+            TestCase { input: "fn = lambda x: logger.error(f'{x}') or logger.error(f'{y}')".to_string(), expected_output: "fn = lambda x: logger.error('%s', x) or logger.error('%s', y)".to_string() },
+            TestCase { input: "[logger.error(f'{é} {x}') for x in xs]".to_string(), expected_output: "[logger.error('%s %s', é, x) for x in xs]".to_string() },
+            TestCase { input: "callback(lambda: logger.error('{}'.format(x)))".to_string(), expected_output: "callback(lambda: logger.error('%s', x))".to_string() },
+            TestCase { input: "callback(lambda: logger.error(f'{x}'))  # printf: skip".to_string(), expected_output: "callback(lambda: logger.error(f'{x}'))  # printf: skip".to_string() },
+            // Loggers in decorators and default values, but not in annotations
+            TestCase { input: "@audit(logger.error(f'{x}'))\ndef f():\n    pass".to_string(), expected_output: "@audit(logger.error('%s', x))\ndef f():\n    pass".to_string() },
+            TestCase { input: "@app.route('/')\n@audit(\n    lambda: logger.error('{}'.format(x)),\n)\nclass C:\n    pass".to_string(), expected_output: "@app.route('/')\n@audit(\n    lambda: logger.error('%s', x),\n)\nclass C:\n    pass".to_string() },
+            TestCase { input: "def f(a, b=logger.error(f'{x}'), *, c=logger.error(f'{y}')):\n    pass".to_string(), expected_output: "def f(a, b=logger.error('%s', x), *, c=logger.error('%s', y)):\n    pass".to_string() },
+            TestCase { input: "async def f(a=logger.error(f'{x}')):\n    pass".to_string(), expected_output: "async def f(a=logger.error('%s', x)):\n    pass".to_string() },
+            TestCase { input: "fn = lambda a=logger.error(f'{x}'): a".to_string(), expected_output: "fn = lambda a=logger.error('%s', x): a".to_string() },
+            TestCase { input: "def f(a: wrap(logger.error(f'{x}')) = 1) -> logger.error(f'{y}'):\n    pass".to_string(), expected_output: "def f(a: wrap(logger.error(f'{x}')) = 1) -> logger.error(f'{y}'):\n    pass".to_string() },
+            TestCase { input: "x: logger.error(f'{y}') = 1".to_string(), expected_output: "x: logger.error(f'{y}') = 1".to_string() },
+            // Bytes targets are skipped
+            TestCase { input: "logger.error(b'{}'.format(x))".to_string(), expected_output: "logger.error(b'{}'.format(x))".to_string() },
+            TestCase { input: "logger.error('{}'.format(x).encode())".to_string(), expected_output: "logger.error('{}'.format(x).encode())".to_string() },
+            // So are methods called on the formatted message, which would apply to the placeholders
+            // instead, like `'%s'.upper()` becoming `'%S'`, or not reach the arguments at all
+            TestCase { input: "logger.error('{} '.format(x).strip(), exc_info=True)".to_string(), expected_output: "logger.error('{} '.format(x).strip(), exc_info=True)".to_string() },
+            TestCase { input: "logger.error(f'{x} '.strip().upper())".to_string(), expected_output: "logger.error(f'{x} '.strip().upper())".to_string() },
+            TestCase { input: "logger.error('{}'.format(x.upper()))".to_string(), expected_output: "logger.error('%s', x.upper())".to_string() },
+            // Bytes arguments use the message's quotes, escaping them and anything that isn't printable ASCII
+            TestCase { input: "logger.error('{}'.format(b\"a'b\"))".to_string(), expected_output: "logger.error('%s', b'a\\'b')".to_string() },
+            TestCase { input: "logger.error(\"{x}\".format(x=b'a\"b'))".to_string(), expected_output: "logger.error(\"%s\", b\"a\\\"b\")".to_string() },
+            TestCase { input: "logger.error('{}'.format(b'\\xff\\x00\\n\\\\'))".to_string(), expected_output: "logger.error('%s', b'\\xff\\x00\\n\\\\')".to_string() },
+            TestCase { input: "logger.error(f'{b\"x\"}')".to_string(), expected_output: "logger.error('%s', b'x')".to_string() },
+            // String keyword arguments keep their quotes
+            TestCase { input: "logger.error('{x}'.format(x=\"it's\"))".to_string(), expected_output: "logger.error('%s', 'it\\'s')".to_string() },
+            // The format() builtin isn't str.format()
+            TestCase { input: "logger.error(format(x, '02d'))".to_string(), expected_output: "logger.error(format(x, '02d'))".to_string() },
+            TestCase { input: "logger.error(format('{}', x), y)".to_string(), expected_output: "logger.error(format('{}', x), y)".to_string() },
+            TestCase { input: "logger.error('{}'.format(format(x, '02d')))".to_string(), expected_output: "logger.error('%s', format(x, '02d'))".to_string() },
+            TestCase { input: "logger.error(f'{format(x, \"02d\")}')".to_string(), expected_output: "logger.error('%s', format(x, '02d'))".to_string() },
+            // .format() on an f-string is skipped
+            TestCase { input: "logger.error(f'{prefix} {{}}'.format(x))".to_string(), expected_output: "logger.error(f'{prefix} {{}}'.format(x))".to_string() },
+            // Anything after the message on its last line is kept
+            TestCase { input: "logger.error('{}'.format(\n\t1\n))\n\nfoo()".to_string(), expected_output: "logger.error('%s', 1)\n\nfoo()".to_string() },
+            TestCase { input: "def f():\n\tlogger.error('{}'.format(\n\t\t1\n\t), exc_info=True)\n\n\ndef g():\n\tlogger.error(f'{2}')\n".to_string(), expected_output: "def f():\n\tlogger.error('%s', 1, exc_info=True)\n\n\ndef g():\n\tlogger.error('%s', 2)\n".to_string() },
+            // Multiline messages followed by multiline arguments, which are left as they are
+            TestCase { input: "logger.error(\n\tf'{a} '\n\tf'{b}', extra={\n\t\t'k': 1,\n\t},\n\texc_info=True,\n)".to_string(), expected_output: "logger.error(\n\t'%s %s', a, b, extra={\n\t\t'k': 1,\n\t},\n\texc_info=True,\n)".to_string() },
+            TestCase { input: "logger.error('{} {}'.format(\n\ta,\n\tb,\n), extra={'k': 1},\n\texc_info=True)\nfoo()".to_string(), expected_output: "logger.error('%s %s', a, b, extra={'k': 1},\n\texc_info=True)\nfoo()".to_string() },
+            TestCase { input: "logger.error(f'{a} '  # first\n\tf'{b}', extra=dict(\n\t\tk=f'{c}',\n\t)); logger.error(f'{d}')".to_string(), expected_output: "logger.error('%s %s', a, b, extra=dict(  # first\n\t\tk=f'{c}',\n\t)); logger.error('%s', d)".to_string() },
         ]
     }
 
     #[tokio::test]
     async fn test_for_regressions() {
-        SETTINGS.get_or_init(|| Opts {
+        for test_case in regression_cases() {
+            run(test_case).await;
+        }
+    }
+
+    #[rustfmt::skip]
+    fn loguru_test_cases() -> Vec<TestCase> {
+        vec![
+            // Already correct brace-style call -- expect no change
+            TestCase { input: "logger.info('{} items', n)".to_string(), expected_output: "logger.info('{} items', n)".to_string() },
+            // str.format() is formatted eagerly too, so it's converted to positional braces
+            TestCase { input: "logger.error('{} items'.format(n))".to_string(), expected_output: "logger.error('{} items', n)".to_string() },
+            TestCase { input: "logger.error('{x!r:>10} and {} at 50%'.format(a, x=b))".to_string(), expected_output: "logger.error('{!r:>10} and {} at 50%', b, a)".to_string() },
+            TestCase { input: "logger.error('{1} {0:^5}'.format(a, b))".to_string(), expected_output: "logger.error('{} {:^5}', b, a)".to_string() },
+            // f-strings are converted to positional braces
+            TestCase { input: "logger.error(f'{n} items')".to_string(), expected_output: "logger.error('{} items', n)".to_string() },
+            TestCase { input: "logger.error(f'{a.b} and {len(c)}')".to_string(), expected_output: "logger.error('{} and {}', a.b, len(c))".to_string() },
+            // Format specs are kept as is
+            TestCase { input: "logger.error(f'{n:>10}')".to_string(), expected_output: "logger.error('{:>10}', n)".to_string() },
+            TestCase { input: "logger.error(f'{n!r}')".to_string(), expected_output: "logger.error('{!r}', n)".to_string() },
+            // Literal braces are re-escaped
+            TestCase { input: "logger.error(f'{{literal}} {n}')".to_string(), expected_output: "logger.error('{{literal}} {}', n)".to_string() },
+        ]
+    }
+
+    #[tokio::test]
+    async fn test_nested_statements() {
+        // A logger is found wherever it sits in the statement tree
+        #[rustfmt::skip]
+        let bodies = [
+            "async def f():\n    logger.error(f'{x}')",
+            "def f():\n    def g():\n        logger.error(f'{x}')",
+            "class A:\n    class B:\n        async def f(self):\n            logger.error(f'{x}')",
+            "@decorator(1)\ndef f():\n    logger.error(f'{x}')",
+            "with a as b, c:\n    logger.error(f'{x}')",
+            "async def f():\n    async with a:\n        logger.error(f'{x}')",
+            "async def f():\n    async for a in b:\n        logger.error(f'{x}')",
+            "for a in b:\n    pass\nelse:\n    logger.error(f'{x}')",
+            "while a:\n    pass\nelse:\n    logger.error(f'{x}')",
+            "if a:\n    pass\nelif b:\n    pass\nelse:\n    logger.error(f'{x}')",
+            "try:\n    logger.error(f'{x}')\nexcept ValueError:\n    pass",
+            "try:\n    pass\nexcept (ValueError, KeyError) as e:\n    logger.error(f'{x}')",
+            "try:\n    pass\nexcept ValueError:\n    pass\nelse:\n    logger.error(f'{x}')",
+            "try:\n    pass\nfinally:\n    logger.error(f'{x}')",
+            "match a:\n    case [b, *c]:\n        pass\n    case {'k': d} if d:\n        logger.error(f'{x}')",
+            "match a:\n    case _:\n        logger.error(f'{x}')",
+            "result = [logger.error(f'{x}') for x in xs]",
+            "callback = lambda: logger.error(f'{x}')",
+            "def f():\n    return a and logger.error(f'{x}')",
+            "def f():\n    return logger.error(f'{x}')",
+            "def f():\n    yield logger.error(f'{x}')",
+            "def f():\n    value = yield from logger.error(f'{x}') or []",
+            "async def f():\n    await logger.error(f'{x}')",
+            "async def f():\n    return (await logger.error(f'{x}'), 1)",
+        ];
+        for body in bodies {
+            run(TestCase {
+                input: body.to_string(),
+                expected_output: body.replace("logger.error(f'{x}')", "logger.error('%s', x)"),
+            })
+            .await;
+        }
+
+        // Sharing a line with another logger, after one spread over several lines
+        run(TestCase {
+            input:
+                "def f():\n    return logger.error(\n        f'{x}'\n    ), logger.error(f'{y}')"
+                    .to_string(),
+            expected_output:
+                "def f():\n    return logger.error(\n        '%s', x\n    ), logger.error('%s', y)"
+                    .to_string(),
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    async fn test_multiple_multiline_changes() {
+        let input = "\
+def f():
+    logger.error(
+        f'{a} '
+        f'{b}'
+    )
+    x = 1
+
+
+class A:
+    def g(self):
+        if x:
+            logger.error(
+                f'{c}'
+                f'{d}',
+                exc_info=True,
+            )
+        logger.error(f'{e}')
+";
+        let expected_output = "\
+def f():
+    logger.error(
+        '%s %s', a, b
+    )
+    x = 1
+
+
+class A:
+    def g(self):
+        if x:
+            logger.error(
+                '%s%s', c, d,
+                exc_info=True,
+            )
+        logger.error('%s', e)
+";
+        run(TestCase {
+            input: input.to_string(),
+            expected_output: expected_output.to_string(),
+        })
+        .await;
+
+        // An assert's message is found before the logger in its test
+        let assert_opts = Opts {
+            convert_asserts: true,
+            ..opts()
+        };
+        run_with_opts(
+            TestCase {
+                input: concat!(
+                    "assert logger.error(\n    f'{a}'\n), (\n    f'{b}'\n    f'{c}'\n)\n",
+                    "logger.error(f'{d}')",
+                )
+                .to_string(),
+                expected_output: concat!(
+                    "assert logger.error(\n    '%s', a\n), (\n    '{}{}'.format(b, c)\n)\n",
+                    "logger.error('%s', d)",
+                )
+                .to_string(),
+            },
+            &assert_opts,
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn test_change_content_loguru() {
+        let opts = Opts {
+            logger_flavor: LoggerFlavor::Loguru,
+            ..opts()
+        };
+        for test_case in loguru_test_cases() {
+            run_with_opts(test_case, &opts).await;
+        }
+    }
+
+    #[rustfmt::skip]
+    fn line_length_test_cases() -> Vec<TestCase> {
+        vec![
+            // Fits -- stays on one line
+            TestCase { input: "logger.error(f'{a} and {b}')".to_string(), expected_output: "logger.error('%s and %s', a, b)".to_string() },
+            // Too long -- spread over several lines
+            TestCase { input: "def foo():\n\tlogger.error(f'a long message about {something} and {something_else}')".to_string(), expected_output: "def foo():\n\tlogger.error(\n\t\t'a long message about %s and %s',\n\t\tsomething,\n\t\tsomething_else,\n\t)".to_string() },
+            // Trailing arguments get a line each too
+            TestCase { input: "logger.error(f'a long message about {something}', exc_info=True)".to_string(), expected_output: "logger.error(\n    'a long message about %s',\n    something,\n    exc_info=True,\n)".to_string() },
+            TestCase { input: "x = logger.error(f'a long message about {a}', b(c, d), 'e, )',) or f".to_string(), expected_output: "x = logger.error(\n    'a long message about %s',\n    a,\n    b(c, d),\n    'e, )',\n) or f".to_string() },
+            // Unless they continue on the next lines
+            TestCase { input: "logger.error(f'a long message about {something}', x,\n    exc_info=True)".to_string(), expected_output: "logger.error(\n    'a long message about %s',\n    something, x,\n    exc_info=True)".to_string() },
+            // Other loggers on the line are measured along with the ones before them
+            TestCase { input: "logger.error(f'a long message about {something}'); logger.error(f'{x}')".to_string(), expected_output: "logger.error(\n    'a long message about %s',\n    something,\n); logger.error(\n    '%s',\n    x,\n)".to_string() },
+            TestCase { input: "logger.error(f'a long message about {something}', x); logger.error(f'{y}')".to_string(), expected_output: "logger.error(\n    'a long message about %s',\n    something,\n    x,\n); logger.error(\n    '%s',\n    y,\n)".to_string() },
+            // Multi-line, collapsed message is too long
+            TestCase { input: "logger.error(\n    f'a long message about {something} '\n    f'and {something_else}',\n    exc_info=True,\n)\nfoo = 1".to_string(), expected_output: "logger.error(\n    'a long message about %s and %s',\n    something,\n    something_else,\n    exc_info=True,\n)\nfoo = 1".to_string() },
+        ]
+    }
+
+    #[tokio::test]
+    async fn test_change_content_line_length() {
+        let opts = Opts {
+            line_length: Some(40),
+            ..opts()
+        };
+        for test_case in line_length_test_cases() {
+            run_with_opts(test_case, &opts).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_change_content_indentation() {
+        let line_length_opts = Opts {
+            line_length: Some(30),
+            ..opts()
+        };
+        let input =
+            |indent: &str| format!("if x:\n{indent}logger.error(f'{{something}} and {{other}}')");
+        let expected = |indent: &str, inner: &str| {
+            format!("if x:\n{indent}logger.error(\n{indent}{inner}'%s and %s',\n{indent}{inner}something,\n{indent}{inner}other,\n{indent})")
+        };
+
+        // Detected from the file
+        for indent in ["  ", "    ", "\t"] {
+            run_with_opts(
+                TestCase {
+                    input: input(indent),
+                    expected_output: expected(indent, indent),
+                },
+                &line_length_opts,
+            )
+            .await;
+        }
+
+        // Set explicitly
+        let two_space_opts = Opts {
+            indent_width: Some(2),
+            ..line_length_opts.clone()
+        };
+        run_with_opts(
+            TestCase {
+                input: input("    "),
+                expected_output: expected("    ", "  "),
+            },
+            &two_space_opts,
+        )
+        .await;
+        let tab_opts = Opts {
+            use_tabs: true,
+            ..line_length_opts.clone()
+        };
+        run_with_opts(
+            TestCase {
+                input: input("    "),
+                expected_output: expected("    ", "\t"),
+            },
+            &tab_opts,
+        )
+        .await;
+
+        // Nothing to go by
+        run_with_opts(
+            TestCase {
+                input: "logger.error(f'{something} and {other}')".to_string(),
+                expected_output: expected("", "    ").replacen("if x:\n", "", 1),
+            },
+            &line_length_opts,
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn test_change_content_without_line_length() {
+        run(TestCase {
+            input: "logger.error(f'a long message about {something} and {something_else}')"
+                .to_string(),
+            expected_output:
+                "logger.error('a long message about %s and %s', something, something_else)"
+                    .to_string(),
+        })
+        .await;
+    }
+
+    #[rustfmt::skip]
+    fn forced_quote_test_cases() -> Vec<TestCase> {
+        vec![
+            // Quote is forced
+            TestCase { input: "logger.error(f\"{x}\")".to_string(), expected_output: "logger.error('%s', x)".to_string() },
+            // A single quote in the content is escaped
+            TestCase { input: "logger.error(f\"it's {x}\")".to_string(), expected_output: "logger.error('it\\'s %s', x)".to_string() },
+            // Content full of apostrophes falls back to double quotes
+            TestCase { input: "logger.error(f\"it's {x}'s and {y}'s\")".to_string(), expected_output: "logger.error(\"it's %s's and %s's\", x, y)".to_string() },
+            // Both quotes present, so there's nothing to gain from falling back
+            TestCase { input: "logger.error(f\"it's {x}'s \\\"{y}\\\"\")".to_string(), expected_output: "logger.error('it\\'s %s\\'s \"%s\"', x, y)".to_string() },
+        ]
+    }
+
+    #[tokio::test]
+    async fn test_change_content_forced_quote() {
+        let opts = Opts {
+            quote: Some(QuoteStyle::Single),
+            ..opts()
+        };
+        for test_case in forced_quote_test_cases() {
+            run_with_opts(test_case, &opts).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_repeated_keyword_with_different_specs() {
+        run(TestCase {
+            input: "logger.error('{x:02f} + {x:03f} - {x} == {y}'.format(x=2, y=2))".to_string(),
+            expected_output: "logger.error('%02f + %03f - %s == %s', 2, 2, 2, 2)".to_string(),
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    async fn test_keyword_arguments_with_variables() {
+        for (input, expected_output) in [
+            (
+                "logger.error('{x} {x}'.format(x=foo))",
+                "logger.error('%s %s', foo, foo)",
+            ),
+            (
+                "logger.error('{x:02f} + {x:03f}'.format(x=foo))",
+                "logger.error('%02f + %03f', foo, foo)",
+            ),
+            (
+                "logger.error('{a} {b}'.format(b=x, a=y))",
+                "logger.error('%s %s', y, x)",
+            ),
+            // `**kwargs` could fill any placeholder, and a placeholder without an argument fails anyway
+            (
+                "logger.error('{a} {b}'.format(**kwargs))",
+                "logger.error('{a} {b}'.format(**kwargs))",
+            ),
+            (
+                "logger.error('{a} {b}'.format(a=x))",
+                "logger.error('{a} {b}'.format(a=x))",
+            ),
+        ] {
+            run(TestCase {
+                input: input.to_string(),
+                expected_output: expected_output.to_string(),
+            })
+            .await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_exception_matches_error_threshold() {
+        let converted = |method: &str| TestCase {
+            input: format!("logger.{method}(f'{{x}}')"),
+            expected_output: format!("logger.{method}('%s', x)"),
+        };
+        let untouched = |method: &str| TestCase {
+            input: format!("logger.{method}(f'{{x}}')"),
+            expected_output: format!("logger.{method}(f'{{x}}')"),
+        };
+
+        let error_opts = Opts {
             log_level: LogLevel::Error,
-            filenames: vec![],
+            ..opts()
+        };
+        run_with_opts(converted("error"), &error_opts).await;
+        run_with_opts(converted("exception"), &error_opts).await;
+
+        let critical_opts = Opts {
+            log_level: LogLevel::Critical,
+            ..opts()
+        };
+        run_with_opts(untouched("error"), &critical_opts).await;
+        run_with_opts(untouched("exception"), &critical_opts).await;
+        run_with_opts(converted("critical"), &critical_opts).await;
+    }
+
+    #[tokio::test]
+    async fn test_explicit_levels() {
+        let converted = |method: &str| TestCase {
+            input: format!("logger.{method}(f'{{x}}')"),
+            expected_output: format!("logger.{method}('%s', x)"),
+        };
+        let untouched = |method: &str| TestCase {
+            input: format!("logger.{method}(f'{{x}}')"),
+            expected_output: format!("logger.{method}(f'{{x}}')"),
+        };
+
+        // Takes precedence over the log level threshold
+        let levels_opts = Opts::parse_from([
+            "printf-log-formatter",
+            "--levels",
+            "info,warning",
+            "--log-level",
+            "error",
+            "test.py",
+        ]);
+        assert_eq!(levels_opts.levels, vec![LogLevel::Info, LogLevel::Warning]);
+
+        run_with_opts(converted("info"), &levels_opts).await;
+        run_with_opts(converted("warning"), &levels_opts).await;
+        run_with_opts(untouched("debug"), &levels_opts).await;
+        run_with_opts(untouched("error"), &levels_opts).await;
+        run_with_opts(untouched("exception"), &levels_opts).await;
+    }
+
+    #[tokio::test]
+    async fn test_change_content_convert_print() {
+        let opts = Opts {
+            convert_print: Some(LogLevel::Info),
+            ..opts()
+        };
+        #[rustfmt::skip]
+        let test_cases = vec![
+            TestCase { input: "print(f'{x}')".to_string(), expected_output: "logger.info('%s', x)".to_string() },
+            TestCase { input: "print('{} {}'.format(x, y))".to_string(), expected_output: "logger.info('%s %s', x, y)".to_string() },
+            TestCase { input: "print(\"hi\")".to_string(), expected_output: "print(\"hi\")".to_string() },
+            TestCase { input: "print(f'{x}', file=sys.stderr)".to_string(), expected_output: "print(f'{x}', file=sys.stderr)".to_string() },
+        ];
+        for test_case in test_cases {
+            run_with_opts(test_case, &opts).await;
+        }
+
+        // Opt-in only
+        run(TestCase {
+            input: "print(f'{x}')".to_string(),
+            expected_output: "print(f'{x}')".to_string(),
+        })
+        .await;
+
+        let opts = Opts {
+            convert_print: Some(LogLevel::Warning),
+            print_logger_name: "log".to_string(),
+            ..opts
+        };
+        run_with_opts(
+            TestCase {
+                input: "if x:\n    print(f'{x}')".to_string(),
+                expected_output: "if x:\n    log.warning('%s', x)".to_string(),
+            },
+            &opts,
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn test_change_content_convert_asserts() {
+        let assert_opts = Opts {
+            convert_asserts: true,
+            // Assert messages are left on their line
+            line_length: Some(40),
+            ..opts()
+        };
+        #[rustfmt::skip]
+        let test_cases = vec![
+            TestCase { input: "assert x, f'{x!r} was wrong'".to_string(), expected_output: "assert x, '{!r} was wrong'.format(x)".to_string() },
+            TestCase { input: "assert len(a) == 2, f'{len(a):>3} items {{ok}}, 100%'".to_string(), expected_output: "assert len(a) == 2, '{:>3} items {{ok}}, 100%'.format(len(a))".to_string() },
+            TestCase { input: "assert x, (
+    f'{x} '
+    f'{y}'
+)".to_string(), expected_output: "assert x, (
+    '{} {}'.format(x, y)
+)".to_string() },
+            // Nothing to move out of the message
+            TestCase { input: "assert x, f'plain'".to_string(), expected_output: "assert x, f'plain'".to_string() },
+            TestCase { input: "assert x, f'{x}'  # printf: skip".to_string(), expected_output: "assert x, f'{x}'  # printf: skip".to_string() },
+            // Loggers in the assert are still handled as loggers
+            TestCase { input: "assert x, logger.error(f'{x}')".to_string(), expected_output: "assert x, logger.error('%s', x)".to_string() },
+        ];
+        for test_case in test_cases {
+            run_with_opts(test_case, &assert_opts).await;
+        }
+
+        // Opt-in only
+        run(TestCase {
+            input: "assert x, f'{x}'".to_string(),
+            expected_output: "assert x, f'{x}'".to_string(),
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    async fn test_change_content_convert_extra() {
+        let extra_opts = Opts {
+            convert_extra: true,
+            // `extra` values are left on their line
+            line_length: Some(60),
+            ..opts()
+        };
+        #[rustfmt::skip]
+        let test_cases = vec![
+            TestCase { input: "logger.error(f'{a}', extra={'detail': f'{b}'})".to_string(), expected_output: "logger.error('%s', a, extra={'detail': '%s' % (b,)})".to_string() },
+            TestCase { input: "logger.error('plain', extra={'a': f'{b!r} at {c:.2f}, 100%', 'd': f'plain', **e})".to_string(), expected_output: "logger.error('plain', extra={'a': '%r at %.2f, 100%%' % (b, c), 'd': f'plain', **e})".to_string() },
+            // A `%` operator formats every conversion, and turns `%%` into `%`
+            TestCase { input: "logger.error('plain', extra={'a': f'{b} took %s', 'c': f'{d} %% done'})".to_string(), expected_output: "logger.error('plain', extra={'a': '%s took %%s' % (b,), 'c': '%s %%%% done' % (d,)})".to_string() },
+            TestCase { input: "logger.error(\n    f'a long message about {something}',\n    extra={'detail': f'{b}'},\n)".to_string(), expected_output: "logger.error(\n    'a long message about %s', something,\n    extra={'detail': '%s' % (b,)},\n)".to_string() },
+            // Spread over several lines, with the `extra` value moving along
+            TestCase { input: "logger.error(f'a long message about {something}', extra={'a': f'{b}'})".to_string(), expected_output: "logger.error(\n    'a long message about %s',\n    something,\n    extra={'a': '%s' % (b,)},\n)".to_string() },
+            // Only for loggers, at the levels handled
+            TestCase { input: "logger.info(f'{a}', extra={'detail': f'{b}'})".to_string(), expected_output: "logger.info(f'{a}', extra={'detail': f'{b}'})".to_string() },
+            TestCase { input: "foo(f'{a}', extra={'detail': f'{b}'})".to_string(), expected_output: "foo(f'{a}', extra={'detail': f'{b}'})".to_string() },
+            TestCase { input: "logger.error(f'{a}', extra={'detail': f'{b}'})  # printf: skip".to_string(), expected_output: "logger.error(f'{a}', extra={'detail': f'{b}'})  # printf: skip".to_string() },
+        ];
+        for test_case in test_cases {
+            run_with_opts(test_case, &extra_opts).await;
+        }
+
+        // Opt-in only
+        run(TestCase {
+            input: "logger.error('plain', extra={'detail': f'{b}'})".to_string(),
+            expected_output: "logger.error('plain', extra={'detail': f'{b}'})".to_string(),
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    async fn test_change_content_rename_warn() {
+        let rename_opts = Opts {
+            rename_warn: true,
+            log_level: LogLevel::Warning,
+            ..opts()
+        };
+        #[rustfmt::skip]
+        let test_cases = vec![
+            TestCase { input: "logger.warn(f'{a}')".to_string(), expected_output: "logger.warning('%s', a)".to_string() },
+            TestCase { input: "self.logger.warn('{} and {}'.format(a, b))".to_string(), expected_output: "self.logger.warning('%s and %s', a, b)".to_string() },
+            TestCase { input: "logger.warn(msg=f'{a}', exc_info=True)".to_string(), expected_output: "logger.warning('%s', a, exc_info=True)".to_string() },
+            TestCase { input: "logger.warn(f'{a}'); logger.warn(f'{b}')".to_string(), expected_output: "logger.warning('%s', a); logger.warning('%s', b)".to_string() },
+            TestCase { input: "logger.warn(\n    f'{a}',\n)".to_string(), expected_output: "logger.warning(\n    '%s', a,\n)".to_string() },
+            // Only loggers being converted are renamed
+            TestCase { input: "logger.warn('plain')".to_string(), expected_output: "logger.warn('plain')".to_string() },
+            TestCase { input: "logger.warn(f'{a}')  # printf: skip".to_string(), expected_output: "logger.warn(f'{a}')  # printf: skip".to_string() },
+            TestCase { input: "warnings.warn(f'{a}')".to_string(), expected_output: "warnings.warn(f'{a}')".to_string() },
+            TestCase { input: "logger.warning(f'{a}')".to_string(), expected_output: "logger.warning('%s', a)".to_string() },
+        ];
+        for test_case in test_cases {
+            run_with_opts(test_case, &rename_opts).await;
+        }
+
+        // Opt-in only
+        run_with_opts(
+            TestCase {
+                input: "logger.warn(f'{a}')".to_string(),
+                expected_output: "logger.warn('%s', a)".to_string(),
+            },
+            &Opts {
+                log_level: LogLevel::Warning,
+                ..opts()
+            },
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn test_change_content_annotate_conversions() {
+        let annotate_opts = Opts {
+            annotate_conversions: true,
+            ..opts()
+        };
+        #[rustfmt::skip]
+        let test_cases = vec![
+            TestCase { input: "logger.error(f'{x}')".to_string(), expected_output: "logger.error('%s', x)  # was: f'{x}'".to_string() },
+            TestCase { input: "logger.error('{} and {}'.format(a, b), exc_info=True)".to_string(), expected_output: "logger.error('%s and %s', a, b, exc_info=True)  # was: '{} and {}'.format(a, b)".to_string() },
+            // After any comment already on the line
+            TestCase { input: "logger.error(f'{x}')  # type: ignore".to_string(), expected_output: "logger.error('%s', x)  # type: ignore  # was: f'{x}'".to_string() },
+            TestCase { input: "logger.error(f'{x}'  # first\n\tf'{y}')  # last".to_string(), expected_output: "logger.error('%s%s', x, y)  # first  # last  # was: f'{x}' f'{y}'".to_string() },
+            TestCase { input: "logger.error(\n\tf'{x}',\n\texc_info=True,\n)".to_string(), expected_output: "logger.error(\n\t'%s', x,  # was: f'{x}'\n\texc_info=True,\n)".to_string() },
+            TestCase { input: "logger.error(f'{a}'); logger.error(f'{b}')".to_string(), expected_output: "logger.error('%s', a); logger.error('%s', b)  # was: f'{a}'  # was: f'{b}'".to_string() },
+            TestCase { input: "x = logger.error(f'{a}') \\\n    or y".to_string(), expected_output: "x = logger.error('%s', a) \\\n    or y".to_string() },
+            TestCase { input: "logger.error('plain')".to_string(), expected_output: "logger.error('plain')".to_string() },
+        ];
+        for test_case in test_cases {
+            run_with_opts(test_case, &annotate_opts).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_change_content_format_only_normalize() {
+        let normalize_opts = Opts {
+            format_only_normalize: true,
+            ..opts()
+        };
+        #[rustfmt::skip]
+        let test_cases = vec![
+            TestCase { input: "logger.error(f'{x!r} was wrong')".to_string(), expected_output: "logger.error('{!r} was wrong'.format(x))".to_string() },
+            TestCase { input: "logger.error(f'{a.b:>3} of {len(c)}, 100% {{ok}}', exc_info=True)".to_string(), expected_output: "logger.error('{:>3} of {}, 100% {{ok}}'.format(a.b, len(c)), exc_info=True)".to_string() },
+            TestCase { input: "logger.error(\n    f'{x} '\n    f'{y}'\n)".to_string(), expected_output: "logger.error(\n    '{} {}'.format(x, y)\n)".to_string() },
+            // Without arguments, there's nothing to normalize
+            TestCase { input: "logger.error(f'plain')".to_string(), expected_output: "logger.error(f'plain')".to_string() },
+            // str.format() calls are already normalized
+            TestCase { input: "logger.error('{}'.format(x))".to_string(), expected_output: "logger.error('{}'.format(x))".to_string() },
+        ];
+        for test_case in test_cases {
+            run_with_opts(test_case, &normalize_opts).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_change_content_strip_useless_fstrings() {
+        let strip_opts = Opts {
+            strip_useless_fstrings: true,
+            ..opts()
+        };
+        #[rustfmt::skip]
+        let test_cases = vec![
+            TestCase { input: "logger.error(f'constant')".to_string(), expected_output: "logger.error('constant')".to_string() },
+            TestCase { input: "logger.error(f\"constant\")".to_string(), expected_output: "logger.error(\"constant\")".to_string() },
+            TestCase { input: "logger.error(f\"it's\", exc_info=True)".to_string(), expected_output: "logger.error(\"it's\", exc_info=True)".to_string() },
+            // Escaped braces become plain braces, and percent signs stay as they are
+            TestCase { input: "logger.error(f'{{x}} at 100%')".to_string(), expected_output: "logger.error('{x} at 100%')".to_string() },
+            TestCase { input: "logger.error(\n    f'just '\n    f'text'\n)".to_string(), expected_output: "logger.error(\n    'just text'\n)".to_string() },
+            TestCase { input: "logger.error(f'a' if b else f'c')".to_string(), expected_output: "logger.error('a' if b else 'c')".to_string() },
+            // f-strings with placeholders are converted as usual
+            TestCase { input: "logger.error(f'{x}')".to_string(), expected_output: "logger.error('%s', x)".to_string() },
+        ];
+        for test_case in test_cases {
+            run_with_opts(test_case, &strip_opts).await;
+        }
+
+        let normalize_opts = Opts {
+            format_only_normalize: true,
+            ..strip_opts
+        };
+        run_with_opts(
+            TestCase {
+                input: "logger.error(f'{{x}}')".to_string(),
+                expected_output: "logger.error('{x}')".to_string(),
+            },
+            &normalize_opts,
+        )
+        .await;
+    }
+
+    #[test]
+    fn test_error_message() {
+        assert_eq!(
+            error_message("Failed to find the end", DEFAULT_ISSUE_URL),
+            "Failed to find the end. Please open an issue at \
+             https://github.com/snok/printf-log-formatter/issues/new"
+        );
+        assert_eq!(
+            error_message(
+                "Failed to find the end",
+                "https://git.example.com/tools/issues"
+            ),
+            "Failed to find the end. Please open an issue at https://git.example.com/tools/issues"
+        );
+        assert_eq!(
+            error_message("Failed to find the end", ""),
+            "Failed to find the end"
+        );
+    }
+
+    #[test]
+    fn test_quotes_inside_placeholders() {
+        let quiet_opts = Opts {
+            quiet: true,
+            ..opts()
+        };
+        let double_quote_opts = Opts {
+            quote: Some(QuoteStyle::Double),
+            ..quiet_opts.clone()
+        };
+        #[rustfmt::skip]
+        let test_cases = vec![
+            // Moved out of the message, keys can use either quote
+            (&quiet_opts, "logger.error(f'{d[\"key\"]} {x}')", "logger.error('%s %s', d['key'], x)"),
+            (&quiet_opts, "logger.error(f\"{d['key']}\")", "logger.error(\"%s\", d[\"key\"])"),
+            (&double_quote_opts, "logger.error(f'{d[\"key\"]}')", "logger.error(\"%s\", d[\"key\"])"),
+            // Reusing the quote character is Python 3.12 syntax, so the file is left alone
+            (&quiet_opts, "logger.error(f\"{d[\"key\"]}\")", "logger.error(f\"{d[\"key\"]}\")"),
+        ];
+        for (opts, input, expected_output) in test_cases {
+            let (output, _) = fix_string(input, "test.py", &Config::from_opts(opts));
+            assert_eq!(output, expected_output);
+        }
+    }
+
+    #[test]
+    fn test_reuses_fstring_quotes() {
+        #[rustfmt::skip]
+        let test_cases = vec![
+            ("logger.error(f\"{d[\"key\"]}\")", true),
+            ("logger.error(rf'{d['key']} {x}')", true),
+            ("x = 1\nlogger.error(F\"{d[\"a\"]}\")  # why", true),
+            // Other quotes inside placeholders are fine
+            ("logger.error(f\"{d['key']}\")", false),
+            ("logger.error(f\"{'{'}\")", false),
+            ("logger.error(f\"\"\"{d[\"key\"]}\"\"\")", false),
+            // As are braces outside f-strings, escaped ones, and other f-string errors
+            ("logger.error(\"{d[\"key\"]}\")", false),
+            ("logger.error(f\"{{\" \"}}\")", false),
+            ("logger.error(f\"{}\")", false),
+            ("# f\"{d[\"key\"]}\"\nlogger.error(f'{x}'", false),
+        ];
+        for (source, expected) in test_cases {
+            assert_eq!(reuses_fstring_quotes(source), expected, "{source}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_change_content_module_logging() {
+        let opts = Opts {
+            include_module_logging: true,
+            ..opts()
+        };
+        run_with_opts(
+            TestCase {
+                input: "logging.error(f\"{x}\")".to_string(),
+                expected_output: "logging.error(\"%s\", x)".to_string(),
+            },
+            &opts,
+        )
+        .await;
+        run(TestCase {
+            input: "logging.error(f\"{x}\")".to_string(),
+            expected_output: "logging.error(f\"{x}\")".to_string(),
+        })
+        .await;
+        run(TestCase {
+            input: "logging.error('{}'.format(x))".to_string(),
+            expected_output: "logging.error('{}'.format(x))".to_string(),
+        })
+        .await;
+
+        // The module can be imported under another name, also conditionally
+        let input = "\
+import logging as lg
+try:
+    import logging as log
+except ImportError:
+    log = None
+from logging import getLogger as get_logger
+
+lg.error(f'{a}')
+log.error(f'{b}')
+lg.getLogger(__name__).error(f'{c}')
+get_logger(__name__).error(f'{d}')
+logger = lg.getLogger(__name__)
+logger.error(f'{e}')";
+        // Loggers created from the module are converted either way
+        let loggers_converted = input
+            .replace("(f'{c}')", "('%s', c)")
+            .replace("(f'{d}')", "('%s', d)")
+            .replace("(f'{e}')", "('%s', e)");
+        run(TestCase {
+            input: input.to_string(),
+            expected_output: loggers_converted.clone(),
+        })
+        .await;
+        run_with_opts(
+            TestCase {
+                input: input.to_string(),
+                expected_output: loggers_converted
+                    .replace("(f'{a}')", "('%s', a)")
+                    .replace("(f'{b}')", "('%s', b)"),
+            },
+            &opts,
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn test_change_content_receiver_levels() {
+        let receiver_opts = Opts::parse_from([
+            "printf-log-formatter",
+            "--log-level",
+            "debug",
+            "--receiver-levels",
+            "metrics=error:critical,audit=warning",
+            "a.py",
+        ]);
+        #[rustfmt::skip]
+        let test_cases = vec![
+            TestCase { input: "metrics.error(f'{x}')".to_string(), expected_output: "metrics.error('%s', x)".to_string() },
+            TestCase { input: "self.metrics.critical(f'{x}')".to_string(), expected_output: "self.metrics.critical('%s', x)".to_string() },
+            TestCase { input: "audit.warning(f'{x}')".to_string(), expected_output: "audit.warning('%s', x)".to_string() },
+            // Other levels are left alone for these receivers
+            TestCase { input: "metrics.info(f'{x}')".to_string(), expected_output: "metrics.info(f'{x}')".to_string() },
+            TestCase { input: "self.metrics.info(f'{x}')".to_string(), expected_output: "self.metrics.info(f'{x}')".to_string() },
+            TestCase { input: "audit.error(f'{x}')".to_string(), expected_output: "audit.error(f'{x}')".to_string() },
+            // Other receivers aren't affected
+            TestCase { input: "logger.info(f'{x}')".to_string(), expected_output: "logger.info('%s', x)".to_string() },
+        ];
+        for test_case in test_cases {
+            run_with_opts(test_case, &receiver_opts).await;
+        }
+
+        // A receiver needs levels
+        assert!(Opts::try_parse_from([
+            "printf-log-formatter",
+            "--receiver-levels",
+            "metrics",
+            "a.py"
+        ])
+        .is_err());
+        assert!(Opts::try_parse_from([
+            "printf-log-formatter",
+            "--receiver-levels",
+            "metrics=loud",
+            "a.py"
+        ])
+        .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_change_content_indexed_loggers() {
+        #[rustfmt::skip]
+        let test_cases = vec![
+            TestCase { input: "LOGGERS['app'].error(f'{x}')".to_string(), expected_output: "LOGGERS['app'].error('%s', x)".to_string() },
+            TestCase { input: "self.loggers[0].error(f'{x}')".to_string(), expected_output: "self.loggers[0].error('%s', x)".to_string() },
+            TestCase { input: "get_loggers()[name].error(f'{x}')".to_string(), expected_output: "get_loggers()[name].error('%s', x)".to_string() },
+            // Blacklisted names are still left alone
+            TestCase { input: "messages[0].error(f'{x}')".to_string(), expected_output: "messages[0].error(f'{x}')".to_string() },
+        ];
+        let strict_opts = Opts {
+            strict_loggers: true,
+            ..opts()
+        };
+        for test_case in test_cases {
+            let input = test_case.input.clone();
+            run(test_case).await;
+            // Left alone with `--strict-loggers`
+            run_with_opts(
+                TestCase {
+                    input: input.clone(),
+                    expected_output: input,
+                },
+                &strict_opts,
+            )
+            .await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_change_content_aliased_logger_method() {
+        #[rustfmt::skip]
+        let test_cases = vec![
+            TestCase { input: "log_error = logger.error\nlog_error(f'{x}')".to_string(), expected_output: "log_error = logger.error\nlog_error('%s', x)".to_string() },
+            TestCase { input: "log_error = functools.partial(self.logger.error)\ndef f():\n\tlog_error('{}'.format(x))".to_string(), expected_output: "log_error = functools.partial(self.logger.error)\ndef f():\n\tlog_error('%s', x)".to_string() },
+            // The level comes from the method
+            TestCase { input: "log_info = logger.info\nlog_info(f'{x}')".to_string(), expected_output: "log_info = logger.info\nlog_info(f'{x}')".to_string() },
+            // Module-level logging stays opt-in
+            TestCase { input: "log_error = logging.error\nlog_error(f'{x}')".to_string(), expected_output: "log_error = logging.error\nlog_error(f'{x}')".to_string() },
+            // Names assigned more than once, and partials with bound arguments, are left alone
+            TestCase { input: "log = logger.error\nlog = print_error\nlog(f'{x}')".to_string(), expected_output: "log = logger.error\nlog = print_error\nlog(f'{x}')".to_string() },
+            TestCase { input: "log = partial(logger.error, 'prefix %s')\nlog(f'{x}')".to_string(), expected_output: "log = partial(logger.error, 'prefix %s')\nlog(f'{x}')".to_string() },
+            TestCase { input: "log = warnings.warn\nlog(f'{x}')".to_string(), expected_output: "log = warnings.warn\nlog(f'{x}')".to_string() },
+        ];
+        for test_case in test_cases {
+            run(test_case).await;
+        }
+
+        // Left alone with `--strict-loggers`
+        let strict_opts = Opts {
+            strict_loggers: true,
+            ..opts()
+        };
+        let input = "log_error = logger.error\nlog_error(f'{x}')".to_string();
+        run_with_opts(
+            TestCase {
+                input: input.clone(),
+                expected_output: input,
+            },
+            &strict_opts,
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn test_change_content_keyword_message() {
+        #[rustfmt::skip]
+        let test_cases = vec![
+            // The message becomes positional, so the arguments can follow it
+            TestCase { input: "logger.error(msg=f'{x}', exc_info=True)".to_string(), expected_output: "logger.error('%s', x, exc_info=True)".to_string() },
+            TestCase { input: "logger.error(msg = '{}'.format(x))".to_string(), expected_output: "logger.error('%s', x)".to_string() },
+            TestCase { input: "logger.error(\n    msg=f'{x}',\n    extra={'a': 1},\n)".to_string(), expected_output: "logger.error(\n    '%s', x,\n    extra={'a': 1},\n)".to_string() },
+            // Other keywords can't be followed by positional arguments
+            TestCase { input: "logger.error(exc_info=True, msg=f'{x}')".to_string(), expected_output: "logger.error(exc_info=True, msg=f'{x}')".to_string() },
+            TestCase { input: "logger.error(msg='plain', exc_info=True)".to_string(), expected_output: "logger.error(msg='plain', exc_info=True)".to_string() },
+        ];
+        for test_case in test_cases {
+            run(test_case).await;
+        }
+
+        let index_opts = Opts {
+            message_arg_index: 1,
+            ..opts()
+        };
+        run_with_opts(
+            TestCase {
+                input: "audit_log.error(request, msg=f'{x}')".to_string(),
+                expected_output: "audit_log.error(request, '%s', x)".to_string(),
+            },
+            &index_opts,
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn test_change_content_message_arg_index() {
+        let index_opts = Opts {
+            message_arg_index: 1,
+            ..opts()
+        };
+        #[rustfmt::skip]
+        let test_cases = vec![
+            TestCase { input: "audit_log.error(request, f'{x}')".to_string(), expected_output: "audit_log.error(request, '%s', x)".to_string() },
+            TestCase { input: "audit_log.error(f'{request}', '{}'.format(x))".to_string(), expected_output: "audit_log.error(f'{request}', '%s', x)".to_string() },
+            // Nothing at the index
+            TestCase { input: "audit_log.error(f'{x}')".to_string(), expected_output: "audit_log.error(f'{x}')".to_string() },
+        ];
+        for test_case in test_cases {
+            run_with_opts(test_case, &index_opts).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_change_content_parse_fallback() {
+        #[rustfmt::skip]
+        let unsupported = vec![
+            TestCase { input: "logger.error(f'{a or b} {x}')".to_string(), expected_output: "logger.error('%s %s', a or b, x)".to_string() },
+            TestCase { input: "logger.error('{}'.format(x < y))".to_string(), expected_output: "logger.error('%s', x < y)".to_string() },
+            TestCase { input: "logger.error(f'{len(a or b) + 1}')".to_string(), expected_output: "logger.error('%s', len(a or b) + 1)".to_string() },
+        ];
+        let fallback_opts = Opts {
+            force_parse_fallback: true,
+            ..opts()
+        };
+        let quiet_opts = Opts {
+            quiet: true,
+            ..opts()
+        };
+        for test_case in unsupported {
+            let input = test_case.input.clone();
+            run_with_opts(test_case, &fallback_opts).await;
+            // Left alone by default
+            run_with_opts(
+                TestCase {
+                    input: input.clone(),
+                    expected_output: input,
+                },
+                &quiet_opts,
+            )
+            .await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_change_content_existing_percent() {
+        #[rustfmt::skip]
+        let mixed = vec![
+            TestCase { input: "logger.error(f'{x} took %d ms', elapsed)".to_string(), expected_output: "logger.error('%s took %d ms', x, elapsed)".to_string() },
+            TestCase { input: "logger.error('{} took %s'.format(x), elapsed)".to_string(), expected_output: "logger.error('%s took %s', x, elapsed)".to_string() },
+            TestCase { input: "logger.error(\n\tf'foo {bar} '\n\tf'baz %s',\n\te\n)".to_string(), expected_output: "logger.error(\n\t'foo %s baz %s', bar,\n\te\n)".to_string() },
+            // Without arguments following the message, existing conversions weren't formatted either
+            TestCase { input: "logger.error(f'{x} took %s')".to_string(), expected_output: "logger.error('%s took %%s', x)".to_string() },
+            TestCase { input: "logger.error('{} took %(ms)s'.format(x))".to_string(), expected_output: "logger.error('%s took %%(ms)s', x)".to_string() },
+            TestCase { input: "logger.error('{:%H:%M} took %s'.format(t))".to_string(), expected_output: "logger.error('%s took %%s', t)".to_string() },
+        ];
+        #[rustfmt::skip]
+        let literal = vec![
+            // A literal percent sign is escaped, since the logger now formats the message
+            TestCase { input: "logger.error(f'{x} is at 100%')".to_string(), expected_output: "logger.error('%s is at 100%%', x)".to_string() },
+            TestCase { input: "logger.error('{}% of {:%Y-%m-%d}'.format(x, d))".to_string(), expected_output: "logger.error('%s%% of %s', x, d)".to_string() },
+            TestCase { input: "logger.error(f'50% done, {x}')".to_string(), expected_output: "logger.error('50%% done, %s', x)".to_string() },
+            TestCase { input: "logger.error(f'{x} %% done')".to_string(), expected_output: "logger.error('%s %%%% done', x)".to_string() },
+            // Without arguments the message isn't formatted
+            TestCase { input: "logger.error('at 100%'.format())".to_string(), expected_output: "logger.error('at 100%')".to_string() },
+            // Percent formatting moved out of the message is left as it was
+            TestCase { input: "logger.error(f\"{'%s' % x} done 100%\")".to_string(), expected_output: "logger.error(\"%s done 100%%\", \"%s\" % x)".to_string() },
+            TestCase { input: "logger.error(f'{\"%05.1f%%\" % (a, b)} {c}')".to_string(), expected_output: "logger.error('%s %s', '%05.1f%%' % (a, b), c)".to_string() },
+            TestCase { input: "logger.error('{} {}%'.format('%s' % x, y))".to_string(), expected_output: "logger.error('%s %s%%', '%s' % x, y)".to_string() },
+        ];
+        #[rustfmt::skip]
+        let misplaced = vec![
+            // Existing conversions would take the arguments moved out of the message
+            "logger.error(f'%s took {x}', elapsed)",
+            "logger.error('%d: {}'.format(x), n)",
+            // Named conversions take a mapping, so they can't be mixed with other arguments
+            "logger.error(f'{x} took %(ms)s', d)",
+        ];
+        let respect_opts = Opts {
+            respect_existing_percent: true,
+            quiet: true,
+            ..opts()
+        };
+        for test_case in mixed {
+            let input = test_case.input.clone();
+            run(test_case).await;
+            // Left alone with `--respect-existing-percent`
+            run_with_opts(
+                TestCase {
+                    input: input.clone(),
+                    expected_output: input,
+                },
+                &respect_opts,
+            )
+            .await;
+        }
+        for test_case in literal {
+            let expected_output = test_case.expected_output.clone();
+            let input = test_case.input.clone();
+            run(test_case).await;
+            run_with_opts(
+                TestCase {
+                    input,
+                    expected_output,
+                },
+                &respect_opts,
+            )
+            .await;
+        }
+        let quiet_opts = Opts {
+            quiet: true,
+            ..opts()
+        };
+        for input in misplaced {
+            run_with_opts(
+                TestCase {
+                    input: input.to_string(),
+                    expected_output: input.to_string(),
+                },
+                &quiet_opts,
+            )
+            .await;
+        }
+    }
+
+    #[test]
+    fn test_file_skip_marker() {
+        // Marked files aren't parsed, so they don't fail to parse either
+        let content = "# printf-log-formatter: off\nlogger.error(f'{x}'";
+        let found = find_changes(content, "test.py", &Config::from_opts(&opts()));
+        assert_eq!(found.map(|(changes, _)| changes.len()), Some(0));
+    }
+
+    #[test]
+    fn test_message_without_end_location() {
+        let content = "logger.error(f'{x}')\nlogger.error(f'{y}')".to_string();
+        let quiet_opts = Opts {
+            quiet: true,
+            ..opts()
+        };
+        let config = Config::from_opts(&quiet_opts);
+
+        // Drop the end of the first message, like the parser does for some nodes
+        let mut program = parse_program(&content, "test.py").unwrap();
+        first_call_args(&mut program)[0].end_location = None;
+
+        // The logger is skipped, and the rest of the file still handled
+        let changes = visit_program(&program, &content, &config);
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].lineno, 2);
+
+        // Arguments copied from the source with --force-parse-fallback need their end too
+        let content = "logger.error(f'{a or b}')".to_string();
+        let fallback_config = Config::from_opts(&Opts {
+            force_parse_fallback: true,
+            ..quiet_opts
         });
-        for test_case in regression_cases() {
+        let mut program = parse_program(&content, "test.py").unwrap();
+        let ExprKind::JoinedStr { values } = &mut first_call_args(&mut program)[0].node else {
+            unreachable!()
+        };
+        let ExprKind::FormattedValue { value, .. } = &mut values[0].node else {
+            unreachable!()
+        };
+        value.end_location = None;
+        assert!(visit_program(&program, &content, &fallback_config).is_empty());
+    }
+
+    #[test]
+    fn test_empty_and_nested_fstring_pieces() {
+        let content = "logger.error(f'{x} and {y}')".to_string();
+        let config = Config::from_opts(&opts());
+
+        // Lead with an empty constant, and nest the rest in another f-string
+        let mut program = parse_program(&content, "test.py").unwrap();
+        let args = first_call_args(&mut program);
+        let ExprKind::JoinedStr { values } = &mut args[0].node else {
+            unreachable!()
+        };
+        let mut empty = values[1].clone();
+        empty.node = ExprKind::Constant {
+            value: Constant::Str(String::new()),
+            kind: None,
+        };
+        let mut nested = args[0].clone();
+        let ExprKind::JoinedStr {
+            values: nested_values,
+        } = &mut nested.node
+        else {
+            unreachable!()
+        };
+        nested_values.remove(0);
+        nested_values.push(empty.clone());
+        let ExprKind::JoinedStr { values } = &mut args[0].node else {
+            unreachable!()
+        };
+        values.truncate(1);
+        values.insert(0, empty);
+        values.push(nested);
+
+        let changes = visit_program(&program, &content, &config);
+        assert_eq!(changes[0].new_text(), "'%s and %s', x, y");
+    }
+
+    #[test]
+    fn test_split_fstring_constants() {
+        let content = "logger.error(f'a{b}cc{d}e')".to_string();
+        let config = Config::from_opts(&opts());
+
+        // Split the literal text into a constant per character
+        let mut program = parse_program(&content, "test.py").unwrap();
+        let ExprKind::JoinedStr { values } = &mut first_call_args(&mut program)[0].node else {
+            unreachable!()
+        };
+        let mut split = vec![];
+        for value in values.drain(..) {
+            let ExprKind::Constant {
+                value: Constant::Str(s),
+                ..
+            } = &value.node
+            else {
+                split.push(value);
+                continue;
+            };
+            for c in s.chars() {
+                let mut piece = value.clone();
+                piece.node = ExprKind::Constant {
+                    value: Constant::Str(c.to_string()),
+                    kind: None,
+                };
+                split.push(piece);
+            }
+        }
+        assert_eq!(split.len(), 6);
+        *values = split;
+
+        let changes = visit_program(&program, &content, &config);
+        assert_eq!(changes[0].new_text(), "'a%scc%se', b, d");
+    }
+
+    /// The arguments of the call in a program's first statement, for tests to modify
+    fn first_call_args(program: &mut [Stmt]) -> &mut Vec<Expr> {
+        let StmtKind::Expr { value } = &mut program[0].node else {
+            unreachable!()
+        };
+        let ExprKind::Call { args, .. } = &mut value.node else {
+            unreachable!()
+        };
+        args
+    }
+
+    /// Find the changes in a program, which tests can modify after parsing it
+    fn visit_program(program: &[Stmt], content: &str, config: &Config) -> Vec<Change> {
+        let state = ThreadLocal::new("test.py".to_string(), content.to_string());
+        THREAD_LOCAL_STATE.sync_scope(state, || {
+            let mut visitor = LoggerVisitor {
+                changes: vec![],
+                config,
+                skipped_rows: skipped_rows(content),
+                logging_names: logging_module_names(program),
+                logger_aliases: logger_method_aliases(program),
+                stats: Stats::default(),
+            };
+            program.iter().for_each(|stmt| visitor.visit_stmt(stmt));
+            visitor.changes
+        })
+    }
+
+    #[tokio::test]
+    async fn test_change_content_attributes_and_subscripts() {
+        #[rustfmt::skip]
+        let test_cases = vec![
+            TestCase { input: "logger.error(f'{a[b].c}')".to_string(), expected_output: "logger.error('%s', a[b].c)".to_string() },
+            TestCase { input: "logger.error(f'{a.b[c]}')".to_string(), expected_output: "logger.error('%s', a.b[c])".to_string() },
+            TestCase { input: "logger.error(f'{a[b][c].d}')".to_string(), expected_output: "logger.error('%s', a[b][c].d)".to_string() },
+            TestCase { input: "logger.error(f\"{data['items'][0].name}\")".to_string(), expected_output: "logger.error(\"%s\", data[\"items\"][0].name)".to_string() },
+            TestCase { input: "logger.error(f'{a.b[c].d[e].f}')".to_string(), expected_output: "logger.error('%s', a.b[c].d[e].f)".to_string() },
+            TestCase { input: "logger.error(f'{a().b[c]().d}')".to_string(), expected_output: "logger.error('%s', a().b[c]().d)".to_string() },
+            // Attributes of other expressions
+            TestCase { input: "logger.error(f'{(a + b).c}')".to_string(), expected_output: "logger.error('%s', (a + b).c)".to_string() },
+            TestCase { input: "logger.error(f'{(a + b)[0]} {(f if a else g)()}')".to_string(), expected_output: "logger.error('%s %s', (a + b)[0], (f if a else g)())".to_string() },
+            TestCase { input: "logger.error(f'{\"-\".join(xs).upper()}')".to_string(), expected_output: "logger.error('%s', '-'.join(xs).upper())".to_string() },
+            TestCase { input: "logger.error(f'{(1).real} {(a, b).count}')".to_string(), expected_output: "logger.error('%s %s', (1).real, (a, b).count)".to_string() },
+            TestCase { input: "logger.error(f'{[x for x in y].copy} { {k: v}.keys}')".to_string(), expected_output: "logger.error('%s %s', [x for x in y].copy, {k: v}.keys)".to_string() },
+        ];
+        for test_case in test_cases {
+            run(test_case).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_change_content_conditional_expressions() {
+        #[rustfmt::skip]
+        let test_cases = vec![
+            TestCase { input: "logger.error(f'{a if b else c}')".to_string(), expected_output: "logger.error('%s', a if b else c)".to_string() },
+            TestCase { input: "logger.error(f'{x.y if x else None} {n}')".to_string(), expected_output: "logger.error('%s %s', x.y if x else None, n)".to_string() },
+            TestCase { input: "logger.error(f'{(a if b else c) + 1}')".to_string(), expected_output: "logger.error('%s', (a if b else c) + 1)".to_string() },
+            TestCase { input: "logger.error(f'{(a if b else c).d}')".to_string(), expected_output: "logger.error('%s', (a if b else c).d)".to_string() },
+            TestCase { input: "logger.error('{}'.format(len(a) if a else 0))".to_string(), expected_output: "logger.error('%s', len(a) if a else 0)".to_string() },
+            // Nested conditional expressions keep their parentheses
+            TestCase { input: "logger.error(f'{(a if b else c) if d else e}')".to_string(), expected_output: "logger.error('%s', (a if b else c) if d else e)".to_string() },
+            TestCase { input: "logger.error(f'{a if (b if c else d) else e}')".to_string(), expected_output: "logger.error('%s', a if (b if c else d) else e)".to_string() },
+            TestCase { input: "logger.error(f'{a if b else c if d else e}')".to_string(), expected_output: "logger.error('%s', a if b else (c if d else e))".to_string() },
+            // Comprehensions keep their conditions, and the parentheses around their iterables
+            TestCase { input: "logger.error(f'{[x for x in (a if b else c)]}')".to_string(), expected_output: "logger.error('%s', [x for x in (a if b else c)])".to_string() },
+            TestCase { input: "logger.error(f'{[x for x in y if x if (a if b else c)]}')".to_string(), expected_output: "logger.error('%s', [x for x in y if x if (a if b else c)])".to_string() },
+            TestCase { input: "logger.error(f'{ {k: d[k] for k in d if k} }')".to_string(), expected_output: "logger.error('%s', {k: d[k] for k in d if k})".to_string() },
+        ];
+        for test_case in test_cases {
             run(test_case).await;
         }
+
+        // The reported case, with its string constant branches
+        let info_opts = Opts {
+            log_level: LogLevel::Info,
+            ..opts()
+        };
+        let single_quote_opts = Opts {
+            quote: Some(QuoteStyle::Single),
+            ..info_opts.clone()
+        };
+        let input = "logger.info(f\"  {dir_name}: {dir_path} {'✓' if exists else '✗'}\")";
+        #[rustfmt::skip]
+        let test_cases = vec![
+            (&info_opts, "logger.info(\"  %s: %s %s\", dir_name, dir_path, \"✓\" if exists else \"✗\")"),
+            (&single_quote_opts, "logger.info('  %s: %s %s', dir_name, dir_path, '✓' if exists else '✗')"),
+        ];
+        for (opts, expected_output) in test_cases {
+            run_with_opts(
+                TestCase {
+                    input: input.to_string(),
+                    expected_output: expected_output.to_string(),
+                },
+                opts,
+            )
+            .await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_change_content_message_choice() {
+        #[rustfmt::skip]
+        let test_cases = vec![
+            TestCase { input: "logger.error(f'{x}' if verbose else '{}'.format(x))".to_string(), expected_output: "logger.error('%s' if verbose else '%s', x)".to_string() },
+            TestCase { input: "logger.error(f'{x!r} failed: {e}' if verbose else f'{x} failed: {e}', exc_info=True)".to_string(), expected_output: "logger.error('%r failed: %s' if verbose else '%s failed: %s', x, e, exc_info=True)".to_string() },
+            TestCase { input: "logger.error(\n    f'{x}!'\n    if loud\n    else f'{x}'\n)".to_string(), expected_output: "logger.error(\n    '%s!'\n    if loud\n    else '%s', x\n)".to_string() },
+            // The arguments have to be the same for both messages
+            TestCase { input: "logger.error(f'{x}' if a else f'{y}')".to_string(), expected_output: "logger.error(f'{x}' if a else f'{y}')".to_string() },
+            TestCase { input: "logger.error(f'{x} {y}' if a else f'{y} {x}')".to_string(), expected_output: "logger.error(f'{x} {y}' if a else f'{y} {x}')".to_string() },
+            TestCase { input: "logger.error(f'{x}' if a else 'plain')".to_string(), expected_output: "logger.error(f'{x}' if a else 'plain')".to_string() },
+            // Arguments can't follow a parenthesized message
+            TestCase { input: "logger.error(f'{x}' if a else (f'{x}'))".to_string(), expected_output: "logger.error(f'{x}' if a else (f'{x}'))".to_string() },
+        ];
+        for test_case in test_cases {
+            run(test_case).await;
+        }
+
+        // Each message carries its own arguments when normalizing to str.format()
+        let normalize_opts = Opts {
+            format_only_normalize: true,
+            ..opts()
+        };
+        run_with_opts(
+            TestCase {
+                input: "logger.error(f'{x}' if a else f'{y}')".to_string(),
+                expected_output: "logger.error('{}'.format(x) if a else '{}'.format(y))"
+                    .to_string(),
+            },
+            &normalize_opts,
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn test_change_content_raw_strings() {
+        #[rustfmt::skip]
+        let test_cases = vec![
+            TestCase { input: r"logger.error(r'path\to\{}'.format(x))".to_string(), expected_output: r"logger.error(r'path\to\%s', x)".to_string() },
+            TestCase { input: r"logger.error(rf'\d+ {x} \w')".to_string(), expected_output: r"logger.error(r'\d+ %s \w', x)".to_string() },
+            TestCase { input: r#"logger.error(Rf"{x}\n" 'done')"#.to_string(), expected_output: r#"logger.error(r"%s\ndone", x)"#.to_string() },
+            // Raw strings can't contain newlines, or end with a backslash, so the backslashes are escaped
+            TestCase { input: r"logger.error(rf'{x} \d' '\n')".to_string(), expected_output: "logger.error('%s \\\\d\n', x)".to_string() },
+            TestCase { input: r"logger.error(fr'{x}\\')".to_string(), expected_output: r"logger.error('%s\\\\', x)".to_string() },
+            // Backslashes in other strings are escaped again
+            TestCase { input: r"logger.error(f'{x} C:\\temp')".to_string(), expected_output: r"logger.error('%s C:\\temp', x)".to_string() },
+            TestCase { input: r"logger.error('{} \\d'.format(x))".to_string(), expected_output: r"logger.error('%s \\d', x)".to_string() },
+        ];
+        for test_case in test_cases {
+            run(test_case).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_change_content_operator_spacing() {
+        #[rustfmt::skip]
+        let test_cases = vec![
+            TestCase { input: "logger.error(f'{a+b}')".to_string(), expected_output: "logger.error('%s', a+b)".to_string() },
+            TestCase { input: "logger.error(f'{a + b}')".to_string(), expected_output: "logger.error('%s', a + b)".to_string() },
+            TestCase { input: "logger.error(f'{a*b + c}')".to_string(), expected_output: "logger.error('%s', a*b + c)".to_string() },
+            TestCase { input: "logger.error('{} {}'.format(a-1, b  //  2))".to_string(), expected_output: "logger.error('%s %s', a-1, b  //  2)".to_string() },
+            // Spread over several lines, the operator gets single spaces
+            TestCase { input: "logger.error('{}'.format(a +\n    b))".to_string(), expected_output: "logger.error('%s', a + b)".to_string() },
+        ];
+        for test_case in test_cases {
+            run(test_case).await;
+        }
+    }
+
+    #[rustfmt::skip]
+    fn comment_test_cases() -> Vec<TestCase> {
+        vec![
+            // Comment on the message line of a multi-line call
+            TestCase { input: "logger.error(\n    f'{x}',  # explain\n    exc_info=True,\n)".to_string(), expected_output: "logger.error(\n    '%s', x,  # explain\n    exc_info=True,\n)".to_string() },
+            // Comments between the lines of a multi-line message
+            TestCase { input: "logger.error(\n    f'{x} '  # first\n    f'{y}',  # second\n    exc_info=True,\n)".to_string(), expected_output: "logger.error(\n    '%s %s', x, y,  # first  # second\n    exc_info=True,\n)".to_string() },
+            TestCase { input: "logger.error(  # why\n    f'{x} # not a comment '\n    f'{y}')".to_string(), expected_output: "logger.error(  # why\n    '%s # not a comment %s', x, y)".to_string() },
+            TestCase { input: "logger.error(f'{x} '  # first\n             f'{y}'\n)".to_string(), expected_output: "logger.error('%s %s', x, y  # first\n)".to_string() },
+        ]
+    }
+
+    #[tokio::test]
+    async fn test_change_content_comments() {
+        for test_case in comment_test_cases() {
+            run(test_case).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_change_content_placeholder() {
+        #[rustfmt::skip]
+        let test_cases = vec![
+            ("%r", "logger.error(f'{a} and {b:>5}')", "logger.error('%r and %5s', a, b)"),
+            ("%r", "logger.error('{} {x}'.format(a, x=1))", "logger.error('%r %r', a, 1)"),
+            // Specs without a printf-style equivalent get the placeholder too
+            ("%r", "logger.error(f'{a:^5}')", "logger.error('%r', a)"),
+            // Percent signs already in the message are left as they are
+            ("%-10s", "logger.error(f'{a} %s', b)", "logger.error('%-10s %s', a, b)"),
+        ];
+        for (placeholder, input, expected_output) in test_cases {
+            let placeholder_opts = Opts {
+                placeholder: placeholder.to_string(),
+                ..opts()
+            };
+            run_with_opts(
+                TestCase {
+                    input: input.to_string(),
+                    expected_output: expected_output.to_string(),
+                },
+                &placeholder_opts,
+            )
+            .await;
+        }
+
+        for placeholder in ["{}", "%s %s", "%", "s"] {
+            let args = [
+                "printf-log-formatter",
+                "--placeholder",
+                placeholder,
+                "test.py",
+            ];
+            assert!(Opts::try_parse_from(args).is_err());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_differing_opts_concurrently() {
+        let stdlib_opts = opts();
+        let loguru_opts = Opts {
+            logger_flavor: LoggerFlavor::Loguru,
+            ..opts()
+        };
+        tokio::join!(
+            run_with_opts(
+                TestCase {
+                    input: "logger.error(f'{x}')".to_string(),
+                    expected_output: "logger.error('%s', x)".to_string(),
+                },
+                &stdlib_opts,
+            ),
+            run_with_opts(
+                TestCase {
+                    input: "logger.error(f'{x}')".to_string(),
+                    expected_output: "logger.error('{}', x)".to_string(),
+                },
+                &loguru_opts,
+            ),
+        );
+    }
+
+    #[test]
+    fn test_get_changes_spans() {
+        let opts = Opts {
+            log_level: LogLevel::Info,
+            ..opts()
+        };
+        let content =
+            "logger.error(f'{a}')\nif x:\n    logger.info(\n        'foo {}'.format(b)\n    )";
+        let changes = get_changes(content, "test.py", &Config::from_opts(&opts));
+
+        assert_eq!(changes.len(), 2);
+        let spans = changes
+            .iter()
+            .map(|c| (c.lineno, c.col_offset, c.end_lineno, c.end_col_offset))
+            .collect::<Vec<_>>();
+        assert_eq!(spans, vec![(1, 13, 1, 19), (4, 8, 4, 26)]);
+        assert_eq!(changes[0].new_string_content, "%s");
+        assert_eq!(changes[0].new_string_variables, vec!["a"]);
+        assert_eq!(changes[1].new_string_content, "foo %s");
+        assert_eq!(changes[1].new_string_variables, vec!["b"]);
+    }
+
+    #[test]
+    fn test_eager_args() {
+        let content = "logger.error(f'{a} {expensive()} {b.c[d]} {len(e) + 1}')\n\
+            logger.error('{} {}'.format(x.y, z(1)))\n\
+            logger.error('{}'.format([str(e) for e in es]))";
+        let changes = get_changes(content, "test.py", &Config::from_opts(&opts()));
+
+        assert_eq!(changes[0].eager_args, vec!["expensive()", "len(e) + 1"]);
+        assert_eq!(changes[1].eager_args, vec!["z(1)"]);
+        assert_eq!(changes[2].eager_args, vec!["[str(e) for e in es]"]);
+    }
+
+    #[test]
+    fn test_side_effect_args() {
+        let content = "logger.error(f'{queue.pop()} {len(items)} {next(it)}')\n\
+            logger.error('{}'.format([seen.add(x) for x in xs]))\n\
+            logger.error(f'{a.pop}')";
+        let side_effect_opts = Opts {
+            warn_side_effects: vec!["pop".to_string(), "next".to_string(), "add".to_string()],
+            ..opts()
+        };
+        let changes = get_changes(content, "test.py", &Config::from_opts(&side_effect_opts));
+
+        assert_eq!(changes[0].side_effect_args, vec!["queue.pop()", "next(it)"]);
+        assert_eq!(
+            changes[1].side_effect_args,
+            vec!["[seen.add(x) for x in xs]"]
+        );
+        // Only calls count
+        assert!(changes[2].side_effect_args.is_empty());
+
+        // Nothing is flagged without a list of names
+        let changes = get_changes(content, "test.py", &Config::from_opts(&opts()));
+        assert!(changes.iter().all(|c| c.side_effect_args.is_empty()));
+    }
+
+    #[tokio::test]
+    async fn test_duplicate_args() {
+        let content = "logger.error(f'{x} {y} {x} {x.z}')\n\
+            logger.error('{} {a} {}'.format(1, 1, a=b))\n\
+            logger.error(f'{x} {y}')\n\
+            logger.error('{a} {} {a}'.format(b, a=1))";
+        let duplicate_opts = Opts {
+            warn_duplicate_args: true,
+            ..opts()
+        };
+        let changes = get_changes(content, "test.py", &Config::from_opts(&duplicate_opts));
+
+        assert_eq!(changes[0].duplicate_args, vec!["x"]);
+        assert_eq!(changes[1].duplicate_args, vec!["1"]);
+        assert!(changes[2].duplicate_args.is_empty());
+        // Placeholders can refer to the same argument of `.format()`
+        assert!(changes[3].duplicate_args.is_empty());
+
+        // Only a warning, every placeholder keeps its argument
+        run_with_opts(
+            TestCase {
+                input: "logger.error(f'{x} {x}')".to_string(),
+                expected_output: "logger.error('%s %s', x, x)".to_string(),
+            },
+            &duplicate_opts,
+        )
+        .await;
+    }
+
+    #[test]
+    fn test_validate() {
+        let content = "logger.error(f'{a} failed')\nx = 1\n";
+        let validate_config = Config::from_opts(&Opts {
+            validate: true,
+            ..opts()
+        });
+        let state = ThreadLocal::new("test.py".to_string(), content.to_string());
+        THREAD_LOCAL_STATE.sync_scope(state, || {
+            let mut changes = get_changes(content, "test.py", &validate_config);
+            assert_eq!(
+                fixed_content(content, "test.py", &changes, &validate_config).unwrap(),
+                Some("logger.error('%s failed', a)\nx = 1\n".to_string())
+            );
+
+            // A quote left unescaped, like a bug in picking quotes would
+            changes[0].new_string_content = "%s won't work".to_string();
+            let error = fixed_content(content, "test.py", &changes, &validate_config).unwrap_err();
+            assert_eq!(
+                error.to_string(),
+                error_message(
+                    "Refusing to write `test.py`, since the fixed content no longer parses",
+                    DEFAULT_ISSUE_URL
+                )
+            );
+
+            // Without --validate, it would have been written
+            let config = Config::from_opts(&opts());
+            assert_eq!(
+                fixed_content(content, "test.py", &changes, &config).unwrap(),
+                Some("logger.error('%s won't work', a)\nx = 1\n".to_string())
+            );
+        });
+    }
+
+    #[test]
+    fn test_change_display() {
+        let content = "logger.error(f'{a} and {b}')
+logger.error(
+    f'{c}'
+    f'{d}'
+)";
+        let changes = get_changes(content, "test.py", &Config::from_opts(&opts()));
+
+        assert_eq!(
+            changes[0].to_string(),
+            "test.py:1:14: f'{a} and {b}' -> '%s and %s', a, b"
+        );
+        assert_eq!(
+            changes[1].to_string(),
+            "test.py:3:5: f'{c}'\n    f'{d}' -> '%s%s', c, d"
+        );
+    }
+
+    #[test]
+    fn test_change_to_text_edit() {
+        let content = "logger.error(f'{a}')
+logger.error(
+    f'{c}'
+    f'{d}'
+)";
+        let changes = get_changes(content, "test.py", &Config::from_opts(&opts()));
+
+        assert_eq!(
+            changes[0].to_text_edit(),
+            TextEdit {
+                start: Position {
+                    line: 0,
+                    character: 13
+                },
+                end: Position {
+                    line: 0,
+                    character: 19
+                },
+                new_text: "'%s', a".to_string(),
+            }
+        );
+        assert_eq!(
+            changes[1].to_text_edit(),
+            TextEdit {
+                start: Position {
+                    line: 2,
+                    character: 4
+                },
+                end: Position {
+                    line: 3,
+                    character: 10
+                },
+                new_text: "'%s%s', c, d".to_string(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_fix_many() {
+        let files = vec![
+            (
+                "a.py".to_string(),
+                "logger.error(f'{a}')\nlogger.error('{}'.format(b))".to_string(),
+            ),
+            ("b.py".to_string(), "logger.error('%s', a)".to_string()),
+            ("c.py".to_string(), "logger.error(f'{a}'".to_string()),
+        ];
+        let quiet_opts = Opts {
+            quiet: true,
+            ..opts()
+        };
+
+        assert_eq!(
+            fix_many(&files, &Config::from_opts(&quiet_opts)),
+            vec![
+                (
+                    "a.py".to_string(),
+                    "logger.error('%s', a)\nlogger.error('%s', b)".to_string(),
+                    true
+                ),
+                (
+                    "b.py".to_string(),
+                    "logger.error('%s', a)".to_string(),
+                    false
+                ),
+                ("c.py".to_string(), "logger.error(f'{a}'".to_string(), false),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_config_shared_between_threads() {
+        let config = Config::from_opts(&Opts {
+            quote: Some(QuoteStyle::Double),
+            ..opts()
+        });
+        let content = "logger.error('{:>5} {!r}'.format(a, b))\nlogger.error(f'{c:02d}')";
+
+        let outputs = std::thread::scope(|scope| {
+            let handles = (0..4)
+                .map(|_| scope.spawn(|| fix_string(content, "test.py", &config)))
+                .collect::<Vec<_>>();
+            handles
+                .into_iter()
+                .map(|handle| handle.join().unwrap())
+                .collect::<Vec<_>>()
+        });
+
+        for output in outputs {
+            assert_eq!(
+                output,
+                (
+                    "logger.error(\"%5s %r\", a, b)\nlogger.error(\"%02d\", c)".to_string(),
+                    true
+                )
+            );
+        }
     }
 }
@@ -0,0 +1,192 @@
+pub use crate::cli::{LogLevel, LoggerFlavor, Opts, OutputFormat, QuoteStyle, ReceiverLevels};
+pub use crate::config::Config;
+pub use crate::files::{find_python_files, output_paths, PythonFile};
+pub use crate::fix_file::{
+    fix_file, fix_many, fix_string, get_changes, get_changes_with_stats, try_fix_string,
+};
+pub use crate::progress::{clear_progress, show_progress};
+pub use crate::report::{render_preview, render_report};
+pub use crate::stats::{LevelStats, Stats};
+pub use crate::timings::Timings;
+
+/// Like `eprintln!`, clearing the `--progress` line first, so the message isn't printed
+/// after it on the same line
+macro_rules! emit {
+    ($($arg:tt)*) => {{
+        $crate::progress::clear_progress();
+        eprintln!($($arg)*);
+    }};
+}
+
+mod cli;
+mod config;
+mod files;
+mod fix_file;
+mod gen_visitor;
+mod parse_format;
+mod parse_fstring;
+mod progress;
+mod report;
+mod stats;
+mod timings;
+mod visitor;
+
+// Since a lot of the formatter logic happens on the other side of the Visitor
+// trait passing down filenames and content is tricky.
+// To not have to pass everything down the stack, we rely on global values.
+// Filename and content are specific to each tokio task, so they're thread-local.
+
+#[derive(Debug, Clone)]
+struct ThreadLocal {
+    filename: String,
+    content: String,
+}
+
+impl ThreadLocal {
+    fn new(filename: String, content: String) -> Self {
+        Self { filename, content }
+    }
+}
+
+tokio::task_local! {
+    static THREAD_LOCAL_STATE: ThreadLocal;
+}
+
+/// Returned by `fix_file` for files that can't be parsed, when running
+/// with `--error-on-parse-failure` or `--max-failures`.
+#[derive(Debug)]
+pub struct ParseFailure {
+    pub filename: String,
+}
+
+impl std::fmt::Display for ParseFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Failed to parse `{}`", self.filename)
+    }
+}
+
+impl std::error::Error for ParseFailure {}
+
+/// A logger message that needs to be rewritten.
+///
+/// Line numbers are 1-indexed and column offsets 0-indexed,
+/// spanning the message argument of the logger call.
+#[derive(Debug)]
+pub struct Change {
+    pub filename: String,
+    pub lineno: usize,
+    pub col_offset: usize,
+    pub end_lineno: usize,
+    pub end_col_offset: usize,
+    /// The source code of the message argument being replaced
+    pub original: String,
+    pub new_string_content: String,
+    pub new_string_variables: Vec<String>,
+    pub quote: char,
+    /// Whether the new message is a raw string, like `r"\d+ %s"`
+    pub raw: bool,
+    /// Replacement for the called function, as `(lineno, col_offset, end_col_offset, name)`.
+    /// Only set when converting `print()` calls, or renaming `warn()` with `--rename-warn`.
+    /// The line can come before the message's, like in `logger.warn(\n    f"{x}"\n)`.
+    pub new_call_target: Option<(usize, usize, usize, String)>,
+    /// Whether the arguments are passed to `.format()` on the message, rather than
+    /// following it. Only set when converting assert messages, or with `--format-only-normalize`.
+    pub str_format: bool,
+    /// Whether the arguments are interpolated into the message with `%`, like `"%s" % (x,)`.
+    /// Only set when converting `extra` values, with `--convert-extra`.
+    pub percent_format: bool,
+    /// Arguments moved out of the message that involve calls, and so are
+    /// evaluated even when the logger is disabled
+    pub eager_args: Vec<String>,
+    /// Arguments moved out of the message that call one of the names given
+    /// to `--warn-side-effects`
+    pub side_effect_args: Vec<String>,
+    /// Arguments written more than once in the original message, like `x` in `f"{x} and {x}"`.
+    /// Placeholders sharing one argument of `.format()`, like `{a} {a}`, don't count.
+    pub duplicate_args: Vec<String>,
+}
+
+impl Change {
+    /// The new message, wrapped in its quotes
+    pub fn message(&self) -> String {
+        let prefix = if self.raw { "r" } else { "" };
+        format!(
+            "{prefix}{}{}{}",
+            self.quote, self.new_string_content, self.quote
+        )
+    }
+
+    /// The text replacing the original message: the new message followed by its arguments
+    pub fn new_text(&self) -> String {
+        let mut text = self.message();
+        if self.str_format {
+            text.push_str(&format!(
+                ".format({})",
+                self.new_string_variables.join(", ")
+            ));
+            return text;
+        }
+        if self.percent_format {
+            // Always a tuple, so an argument that is a tuple itself isn't unpacked
+            let variables = self.new_string_variables.join(", ");
+            if self.new_string_variables.len() == 1 {
+                text.push_str(&format!(" % ({variables},)"));
+            } else {
+                text.push_str(&format!(" % ({variables})"));
+            }
+            return text;
+        }
+        for variable in &self.new_string_variables {
+            text.push_str(", ");
+            text.push_str(variable);
+        }
+        text
+    }
+
+    /// The change as an LSP-style text edit, with 0-indexed lines and columns.
+    ///
+    /// Columns count characters rather than UTF-16 code units, so they only
+    /// line up with the LSP spec for lines without characters outside the BMP.
+    pub fn to_text_edit(&self) -> TextEdit {
+        TextEdit {
+            start: Position {
+                line: self.lineno - 1,
+                character: self.col_offset,
+            },
+            end: Position {
+                line: self.end_lineno - 1,
+                character: self.end_col_offset,
+            },
+            new_text: self.new_text(),
+        }
+    }
+}
+
+/// Renders as `filename:line:column: old -> new`, with a 1-indexed column
+impl std::fmt::Display for Change {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}:{}:{}: {} -> {}",
+            self.filename,
+            self.lineno,
+            self.col_offset + 1,
+            self.original,
+            self.new_text()
+        )
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Position {
+    pub line: usize,
+    pub character: usize,
+}
+
+/// A replacement of the text between two positions
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct TextEdit {
+    pub start: Position,
+    pub end: Position,
+    pub new_text: String,
+}
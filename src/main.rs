@@ -1,75 +1,199 @@
-use anyhow::Result;
-use clap::Parser;
-use clap::__derive_refs::once_cell::sync::OnceCell;
+use anyhow::Context;
 use futures::{stream, StreamExt};
+use printf_log_formatter::{
+    clear_progress, find_python_files, fix_file, output_paths, render_preview, render_report,
+    show_progress, try_fix_string, Config, Opts, OutputFormat, ParseFailure, Stats, Timings,
+};
+use std::collections::HashMap;
+use std::io::IsTerminal;
 use std::process::exit;
+use std::time::Instant;
 use tokio::fs;
 
-use crate::cli::Opts;
-use crate::fix_file::fix_file;
-
-mod cli;
-mod fix_file;
-mod gen_visitor;
-mod parse_format;
-mod parse_fstring;
-mod visitor;
-
-// Since a lot of the formatter logic happens on the other side of the Visitor
-// trait passing down filenames and content is tricky.
-// To not have to pass everything down the stack, we rely on global values.
-// Settings are the same for all files, so we make this global, while
-// filename and content are specific to each tokio task, so they're thread-local.
-
-static SETTINGS: OnceCell<Opts> = OnceCell::new();
-
-#[derive(Debug, Clone)]
-struct ThreadLocal {
-    filename: String,
-    content: String,
-}
-
-tokio::task_local! {
-    static THREAD_LOCAL_STATE: ThreadLocal;
-}
-
-#[derive(Debug)]
-pub struct Change {
-    lineno: usize,
-    col_offset: usize,
-    end_lineno: usize,
-    end_col_offset: usize,
-    new_string_content: String,
-    new_string_variables: Vec<String>,
-    quote: char,
-}
+// Exit codes. Invalid arguments also exit with 2, from clap.
+const NOTHING_CHANGED: i32 = 0;
+const CHANGED: i32 = 1;
+const HARD_ERROR: i32 = 2;
 
 #[tokio::main]
-async fn main() -> Result<()> {
+async fn main() {
     // Load arguments
-    let opts = Opts::parse();
-    SETTINGS.set(opts.clone()).unwrap();
+    let started = Instant::now();
+    let opts = match Opts::parse_with_pyproject() {
+        Ok(opts) => opts,
+        Err(error) => error.exit(),
+    };
+    if let Err(error) = opts.validate() {
+        error.exit();
+    }
+    if opts.print_config {
+        print!("{}", opts.render_config());
+        exit(NOTHING_CHANGED);
+    }
+    if let Some(filename) = &opts.stdin_filename {
+        exit(fix_stdin(filename, &Config::from_opts(&opts)));
+    }
 
-    // Filter down filenames to Python files only
-    let filenames = opts.filenames.into_iter().filter(|f| {
-        std::path::Path::new(f)
-            .extension()
-            .map_or(false, |ext| ext.eq_ignore_ascii_case("py"))
-    });
+    // Find the Python files among the paths given, searching directories
+    let python_files = match find_python_files(
+        &opts.filenames,
+        &opts.include,
+        &opts.exclude,
+        opts.output_dir.as_deref(),
+    ) {
+        Ok(python_files) => python_files,
+        Err(err) => {
+            eprintln!("{err:#}");
+            exit(HARD_ERROR);
+        }
+    };
+    let outputs = match &opts.output_dir {
+        Some(output_dir) => match output_paths(output_dir, &python_files) {
+            Ok(paths) => paths.into_iter().map(Some).collect(),
+            Err(err) => {
+                eprintln!("{err:#}");
+                exit(HARD_ERROR);
+            }
+        },
+        None => vec![None; python_files.len()],
+    };
+    let filenames = python_files.into_iter().map(|file| file.filename);
+
+    // Only show progress to people watching, since it's redrawn in place
+    let progress = opts.progress && !opts.quiet && std::io::stderr().is_terminal();
+    let total = outputs.len();
 
-    // Create a tokio task per file
-    let tasks_stream = stream::iter(filenames).map(|filename| async move {
-        let content = fs::read_to_string(&filename).await?;
-        THREAD_LOCAL_STATE
-            .scope(ThreadLocal { filename, content }, fix_file())
+    // Create a future per file. They're all polled from this task,
+    // so they can share a reference to the settings.
+    let config = &Config::from_opts(&opts);
+    // JSON reports show the lines around each change, from before the file was changed
+    let keep_sources = opts.output_format == OutputFormat::Json;
+    let tasks_stream = stream::iter(filenames.zip(outputs)).map(|(filename, output)| async move {
+        let start = Instant::now();
+        let content = fs::read_to_string(&filename)
             .await
+            .with_context(|| format!("Failed to read `{filename}`"))?;
+        let read = start.elapsed();
+        let source = keep_sources.then(|| content.clone());
+        let (changes, stats, mut timings) =
+            fix_file(filename.clone(), content, output, config).await?;
+        timings.read = read;
+        Ok::<_, anyhow::Error>((filename, changes, stats, timings, source))
     });
 
     // Run tasks concurrently
     // *Added a limit of 256 to avoid `too many open files` errors
-    let results = tasks_stream.buffer_unordered(256).collect::<Vec<_>>().await;
+    let mut results = tasks_stream.buffer_unordered(256);
+
+    // Parse failures have already been reported. Other errors, like failing to
+    // read or write a file, are reported here. Either way the other files are still handled,
+    // unless more files than `--max-failures` fail to parse.
+    let mut files = vec![];
+    let mut sources = HashMap::new();
+    let mut stats = Stats::default();
+    let mut timings = Timings::default();
+    let mut hard_error = false;
+    let mut parse_failures = 0;
+    let mut processed = 0;
+    while let Some(result) = results.next().await {
+        processed += 1;
+        match result {
+            Ok((filename, changes, file_stats, file_timings, source)) => {
+                stats.merge(&file_stats);
+                timings.merge(&file_timings);
+                if let Some(source) = source {
+                    sources.insert(filename.clone(), source);
+                }
+                files.push((filename, changes));
+            }
+            Err(err) if err.is::<ParseFailure>() => {
+                hard_error |= opts.error_on_parse_failure;
+                parse_failures += 1;
+                if opts.max_failures.map_or(false, |max| parse_failures >= max) {
+                    clear_progress();
+                    eprintln!("Stopping after {parse_failures} files failed to parse");
+                    hard_error = true;
+                    break;
+                }
+            }
+            Err(err) => {
+                clear_progress();
+                eprintln!("{err:#}");
+                hard_error = true;
+            }
+        }
+        // Drawn after any errors for this file, so it's the last line while running
+        if progress {
+            show_progress(&format!("Processed {processed}/{total} files"));
+        }
+    }
+
+    if progress {
+        eprintln!();
+    }
+
+    if let Some(path) = &opts.stats_json {
+        if let Err(err) = fs::write(path, stats.to_json()).await {
+            eprintln!("Failed to write `{path}`: {err}");
+            hard_error = true;
+        }
+    }
+
+    // Report changes, in a stable order
+    files.sort_by(|a, b| a.0.cmp(&b.0));
+    if opts.preview {
+        if !opts.quiet {
+            print!("{}", render_preview(&files));
+        }
+    } else if (opts.check && !opts.quiet) || opts.output_format != OutputFormat::Text {
+        let report = render_report(opts.output_format, &files, &sources, opts.report_context);
+        print!("{report}");
+    }
+
+    // Only files handled without errors are timed
+    if opts.profile {
+        eprint!("{}", timings.render(started.elapsed()));
+    }
+
+    // Set exit code; 2 for hard errors, like a file that couldn't be read, or parsed with
+    // `--error-on-parse-failure`, 1 if something was (or would be) changed, otherwise 0
+    let something_changed = files.iter().any(|(_, changes)| !changes.is_empty());
+    if hard_error {
+        exit(HARD_ERROR);
+    } else if something_changed {
+        exit(CHANGED);
+    }
+    exit(NOTHING_CHANGED);
+}
 
-    // Set exit code; 1 if something was changed, otherwise 0
-    let something_changed = results.into_iter().any(std::result::Result::unwrap);
-    exit(i32::from(something_changed));
+/// Fix content read from stdin, for editors, and print the result to stdout.
+///
+/// The fixed content is printed either way, and the exit code tells whether it changed.
+/// When the content can't be parsed with `--error-on-parse-failure`, or no longer parses
+/// once fixed with `--validate`, nothing is printed and the exit code is 2.
+fn fix_stdin(filename: &str, config: &Config) -> i32 {
+    let content = match std::io::read_to_string(std::io::stdin()) {
+        Ok(content) => content,
+        Err(err) => {
+            eprintln!("Failed to read stdin: {err}");
+            return HARD_ERROR;
+        }
+    };
+    match try_fix_string(&content, filename, config) {
+        Ok((new_content, changed)) => {
+            print!("{new_content}");
+            if changed {
+                CHANGED
+            } else {
+                NOTHING_CHANGED
+            }
+        }
+        Err(err) => {
+            // Parse failures have already been reported
+            if !err.is::<ParseFailure>() {
+                eprintln!("{err:#}");
+            }
+            HARD_ERROR
+        }
+    }
 }
@@ -1,32 +1,45 @@
-use crate::cli::emit_error;
-use crate::parse_fstring::parse_formatted_value;
-use crate::visitor::constant_to_string;
+use crate::cli::{emit_error, emit_skip, warn_centered_spec, LoggerFlavor};
+use crate::config::Config;
+use crate::parse_fstring::parse_argument;
+use crate::visitor::{escape_percent, PercentEscape};
 use crate::THREAD_LOCAL_STATE;
 use anyhow::bail;
 use anyhow::Result;
-use regex::Regex;
+use regex::{Captures, Regex};
 use rustpython_parser::ast::{Constant, Expr, ExprKind, Keyword, KeywordData};
 
 #[derive(Debug)]
 pub struct NamedArg {
     pub(crate) key: String,
-    pub(crate) value: Constant,
+    pub(crate) value: String,
 }
 
-fn get_named_arg_index_start_end(re: &Regex, string: &str, key: &str) -> Result<(usize, usize)> {
-    for cap in re.captures_iter(string) {
+fn get_named_arg_index_start_end(
+    config: &Config,
+    string: &str,
+    key: &str,
+) -> Result<(usize, usize, String)> {
+    for cap in config.formatted_value_group_regex.captures_iter(string) {
         let capture = cap.get(0).unwrap();
         if cap.get(1).unwrap().as_str() == key {
-            return Ok((capture.start(), capture.end()));
+            let placeholder = placeholder(config, LoggerFlavor::Stdlib, &cap);
+            return Ok((capture.start(), capture.end(), placeholder));
         }
     }
-    emit_error(&format!(
-        "Failed to capture named args for string '{}'",
-        string
-    ));
+    emit_error(
+        config,
+        &format!("Failed to capture named args for string '{}'", string),
+    );
     bail!("Failed to capture named args")
 }
 
+/// The placeholder for a curly brace captured with `FORMATTED_VALUE_GROUP_REGEX`
+fn placeholder(config: &Config, flavor: LoggerFlavor, cap: &Captures) -> String {
+    let conversion = cap.get(2).and_then(|m| m.as_str().chars().next());
+    let spec = cap.get(3).map_or("", |m| m.as_str());
+    flavor.placeholder(config, conversion, spec)
+}
+
 fn get_named_arg_indexes(re: &Regex, string: &str, key: &str) -> Vec<usize> {
     let mut matches = vec![];
     for (i, cap) in re.captures_iter(string).enumerate() {
@@ -41,51 +54,47 @@ pub fn get_args_and_keywords(
     args: &[Expr],
     keywords: &[Keyword],
     quote: char,
+    config: &Config,
 ) -> Result<(Vec<String>, Vec<NamedArg>)> {
     let mut f_named_args: Vec<NamedArg> = vec![];
     let mut f_args: Vec<String> = vec![];
 
     for keyword in keywords {
         let KeywordData { arg, value } = &keyword.node;
-        match &value.node {
-            ExprKind::Constant { value, .. } => {
-                if let Some(arg) = arg {
-                    f_named_args.push(NamedArg {
-                        key: arg.to_string(),
-                        value: value.clone(),
-                    });
-                } else {
-                    f_args.push(constant_to_string(value.clone()));
-                }
-            }
-            ExprKind::Name { id, .. } => f_args.push(id.to_string()),
-            _ => {
-                let filename = THREAD_LOCAL_STATE.with(|tl| tl.filename.clone());
-                emit_error(&format!(
-                    "Failed to parse `{}` line {}",
+        // `**kwargs` could fill any of the named placeholders, so we can't tell which
+        let Some(arg) = arg else {
+            let filename = THREAD_LOCAL_STATE.with(|tl| tl.filename.clone());
+            emit_skip(
+                config,
+                &format!(
+                    "Skipping the logger in `{}` line {}, since it passes `**` arguments to .format()",
                     filename,
                     value.location.row()
-                ));
-                bail!("");
-            }
-        }
+                ),
+            );
+            bail!("");
+        };
+        f_named_args.push(NamedArg {
+            key: arg.to_string(),
+            value: parse_argument(value, quote, config)?,
+        });
     }
 
     for arg in args {
-        f_args.push(parse_formatted_value(arg, String::new(), false, quote)?);
+        f_args.push(parse_argument(arg, quote, config)?);
     }
 
     Ok((f_args, f_named_args))
 }
 
 // Captures any {} in a string
-const FORMATTED_VALUE_REGEX: &str = r"\{.*?\}";
+pub(crate) const FORMATTED_VALUE_REGEX: &str = r"\{.*?\}";
 
-// Captures any {} in a string, but creates a group for
-// {first:second} where second is optional. This lets us separate
-// the variable from the formatting in `{foo:02f}`
+// Captures any {} in a string, but creates groups for
+// {first!second:third} where second and third are optional. This lets us separate
+// the variable from the conversion and formatting in `{foo!r:>10}`
 // TODO: Can't we just use AST?
-const FORMATTED_VALUE_GROUP_REGEX: &str = r"\{([^{}:]*)(?::[^{}]*)?\}";
+pub(crate) const FORMATTED_VALUE_GROUP_REGEX: &str = r"\{([^{}:!]*)(?:!([rsa]))?(?::([^{}]*))?\}";
 
 /// Replace all keyword arguments with %s and insert each of their values
 /// into the `ordered_arguments` vector, in the right order. Something to be
@@ -95,31 +104,36 @@ const FORMATTED_VALUE_GROUP_REGEX: &str = r"\{([^{}:]*)(?::[^{}]*)?\}";
 ///
 /// so we have to handle the potential of multiple indices for one keyword arg,
 /// and we need to separate the variable name from the contents of the curly brace.
+/// Every index gets the same value, but keeps its own format spec, so the
+/// above becomes `"%02f + %03f - %s == %s" % (2, 2, 2, 2)`.
 fn order_keyword_arguments(
+    config: &Config,
     string: &mut str,
     new_string: &mut String,
     f_named_args: Vec<NamedArg>,
     ordered_arguments: &mut [Option<String>],
 ) -> Result<()> {
-    let group_regex = Regex::new(FORMATTED_VALUE_GROUP_REGEX).unwrap();
     for keyword_arg in f_named_args {
         // Get all indexes for the given keyword argument key
-        let indexes = get_named_arg_indexes(&group_regex, string, &keyword_arg.key);
+        let indexes = get_named_arg_indexes(
+            &config.formatted_value_group_regex,
+            string,
+            &keyword_arg.key,
+        );
 
-        // Convert Rust type to a string value
-        let str_value = constant_to_string(keyword_arg.value);
+        let str_value = keyword_arg.value;
 
         // Push each string value to the right index
         // We might push index 1, then 3; not 0,1,2.
         for index in indexes {
-            let (start, end) =
-                get_named_arg_index_start_end(&group_regex, new_string, &keyword_arg.key)?;
+            let (start, end, placeholder) =
+                get_named_arg_index_start_end(config, new_string, &keyword_arg.key)?;
 
             // Insert value into the right index for printf-style formatting later
             ordered_arguments[index] = Some(str_value.clone());
 
             // Replace the curly brace from the string
-            new_string.replace_range(start..end, "%s");
+            new_string.replace_range(start..end, &placeholder);
         }
     }
     Ok(())
@@ -128,13 +142,13 @@ fn order_keyword_arguments(
 // Args are captured in order, so we should be able to just fill in the missing ordered arguments.
 // One nice assumption we can make here is that each arg is unique and only appears once.
 fn order_arguments(
+    config: &Config,
     new_string: &mut String,
     f_args: Vec<String>,
     ordered_arguments: &mut [Option<String>],
 ) {
-    let any_curly_brace_re = Regex::new(FORMATTED_VALUE_REGEX).unwrap();
     for arg in f_args {
-        let Some(mat) = any_curly_brace_re.find(new_string) else {
+        let Some(cap) = config.formatted_value_group_regex.captures(new_string) else {
             // This will happen for syntax like
             //  logger.info("{}".format(1,2))
             // where there are more arguments passed than mapped to.
@@ -143,11 +157,12 @@ fn order_arguments(
             let filename = THREAD_LOCAL_STATE.with(|tl| tl.filename.clone());
             panic!("File `{filename}` contains a str.format call with too many arguments for the string. Argument is `{arg}`. Please fix before proceeding.")
         };
-        let start = mat.start();
-        let end = mat.end();
+        let mat = cap.get(0).unwrap();
+        let (start, end) = (mat.start(), mat.end());
+        let placeholder = placeholder(config, LoggerFlavor::Stdlib, &cap);
 
-        // Replace a {} with %s
-        new_string.replace_range(start..end, "%s");
+        // Replace a {} with %s, or a printf-style equivalent of its conversion and format spec
+        new_string.replace_range(start..end, &placeholder);
 
         // Find the first `None` in the ordered arguments vector and fill it with
         // our argument value. This relies on keyword arguments being populated first.
@@ -160,6 +175,7 @@ fn order_arguments(
 }
 
 fn order(
+    config: &Config,
     string: &mut str,
     new_string: &mut String,
     f_args: Vec<String>,
@@ -167,25 +183,230 @@ fn order(
     ordered_arguments: &mut [Option<String>],
 ) -> Result<()> {
     // Keyword arguments need to be handled first, or the ordered_arguments logic breaks
-    order_keyword_arguments(string, new_string, f_named_args, ordered_arguments)?;
-    order_arguments(new_string, f_args, ordered_arguments);
+    order_keyword_arguments(config, string, new_string, f_named_args, ordered_arguments)?;
+    order_arguments(config, new_string, f_args, ordered_arguments);
     Ok(())
 }
 
+/// How the positional placeholders of a `str.format()` string are numbered
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub(crate) enum Numbering {
+    /// Like `{}`, or no positional placeholders at all
+    Automatic,
+    /// Like `{0}`
+    Manual,
+    /// Both, which Python refuses to format
+    Mixed,
+}
+
+/// Find how the positional placeholders in a string are numbered.
+/// Named ones, like `{x}`, don't count.
+pub(crate) fn numbering(config: &Config, string: &str) -> Numbering {
+    let (mut automatic, mut manual) = (false, false);
+    for cap in config.formatted_value_group_regex.captures_iter(string) {
+        let field = cap.get(1).unwrap().as_str();
+        // The argument is the part before any attribute or index, like `0` in `{0.name}`
+        let argument = field.split(['.', '[']).next().unwrap_or_default();
+        if argument.is_empty() {
+            automatic = true;
+        } else if argument.chars().all(|c| c.is_ascii_digit()) {
+            manual = true;
+        }
+    }
+    match (automatic, manual) {
+        (true, true) => Numbering::Mixed,
+        (false, true) => Numbering::Manual,
+        _ => Numbering::Automatic,
+    }
+}
+
+/// Turn manually numbered placeholders into automatically numbered ones, ordering the arguments
+/// to match, so `"{1} {0} {1}".format(a, b)` is handled like `"{} {} {}".format(b, a, b)`.
+///
+/// Returns `None` for indexes out of range, or fields with attributes or indexes, like `{0.name}`.
+fn renumber(config: &Config, string: &str, args: &[Expr]) -> Option<(String, Vec<Expr>)> {
+    let mut renumbered = String::new();
+    let mut renumbered_args = vec![];
+    let mut last_end = 0;
+    for cap in config.formatted_value_group_regex.captures_iter(string) {
+        let field = cap.get(1).unwrap();
+        let Ok(index) = field.as_str().parse::<usize>() else {
+            // Named placeholders are filled by keyword arguments
+            if field
+                .as_str()
+                .chars()
+                .all(|c| c.is_alphanumeric() || c == '_')
+            {
+                continue;
+            }
+            return None;
+        };
+        renumbered.push_str(&string[last_end..field.start()]);
+        renumbered_args.push(args.get(index)?.clone());
+        last_end = field.end();
+    }
+    renumbered.push_str(&string[last_end..]);
+    Some((renumbered, renumbered_args))
+}
+
+/// Splice `str.format()` calls and f-strings passed as arguments into the message they're
+/// formatted into, so `"{} {}".format(a, "<{}>".format(b))` is handled like
+/// `"{} <{}>".format(a, b)`, and `"{}".format(f"<{b}>")` like `"<{}>".format(b)`.
+///
+/// Both use the same syntax, so this is only a matter of replacing the placeholder with the
+/// nested string, and the argument with the nested arguments. That's only done for placeholders
+/// without a conversion or format spec, and for nested calls and f-strings that
+/// `nested_format_call` and `nested_fstring` accept.
+fn splice_nested_format_calls(
+    config: &Config,
+    string: &str,
+    args: &[Expr],
+    keywords: &[Keyword],
+) -> (String, Vec<Expr>) {
+    // Positional arguments fill the placeholders that aren't named after a keyword, in order
+    let names = keywords
+        .iter()
+        .filter_map(|keyword| keyword.node.arg.as_deref())
+        .collect::<Vec<_>>();
+    let positional = config
+        .formatted_value_group_regex
+        .captures_iter(string)
+        .filter(|cap| !names.contains(&cap.get(1).unwrap().as_str()))
+        .map(|cap| cap.get(0).unwrap().range())
+        .collect::<Vec<_>>();
+
+    let mut spliced = String::new();
+    let mut spliced_args = vec![];
+    let mut last_end = 0;
+    for (i, arg) in args.iter().enumerate() {
+        let nested = nested_format_call(config, arg).or_else(|| nested_fstring(arg));
+        match (positional.get(i), nested) {
+            (Some(range), Some((nested, nested_args))) if &string[range.clone()] == "{}" => {
+                spliced.push_str(&string[last_end..range.start]);
+                spliced.push_str(&nested);
+                spliced_args.extend(nested_args);
+                last_end = range.end;
+            }
+            _ => spliced_args.push(arg.clone()),
+        }
+    }
+    spliced.push_str(&string[last_end..]);
+    (spliced, spliced_args)
+}
+
+/// The string and arguments of a `str.format()` call passed as an argument, with its own
+/// nested calls spliced in. Calls with keyword arguments, or strings with named or numbered
+/// placeholders or literal braces, are left as arguments.
+fn nested_format_call(config: &Config, arg: &Expr) -> Option<(String, Vec<Expr>)> {
+    let ExprKind::Call {
+        func,
+        args,
+        keywords,
+    } = &arg.node
+    else {
+        return None;
+    };
+    let ExprKind::Attribute { value, attr, .. } = &func.node else {
+        return None;
+    };
+    let ExprKind::Constant {
+        value: Constant::Str(string),
+        ..
+    } = &value.node
+    else {
+        return None;
+    };
+    if attr != "format" || !keywords.is_empty() || string.contains("{{") || string.contains("}}") {
+        return None;
+    }
+    let placeholders = config
+        .formatted_value_group_regex
+        .captures_iter(string)
+        .map(|cap| cap.get(1).unwrap().as_str().is_empty())
+        .collect::<Vec<_>>();
+    if placeholders.len() != args.len()
+        || placeholders.len() != config.formatted_value_regex.find_iter(string).count()
+        || placeholders.contains(&false)
+    {
+        return None;
+    }
+    Some(splice_nested_format_calls(config, string, args, &[]))
+}
+
+/// The f-string passed as an argument as a `str.format()` string, like `<{!r}>` for
+/// `f"<{b!r}>"`, along with the expressions in its placeholders. f-strings with literal braces,
+/// or with placeholders in their format specs, like `f"{x:{width}}"`, are left as arguments.
+fn nested_fstring(arg: &Expr) -> Option<(String, Vec<Expr>)> {
+    let ExprKind::JoinedStr { values } = &arg.node else {
+        return None;
+    };
+    let mut string = String::new();
+    let mut args = vec![];
+    for value in values {
+        match &value.node {
+            ExprKind::Constant {
+                value: Constant::Str(s),
+                ..
+            } if !s.contains(['{', '}']) => string.push_str(s),
+            ExprKind::FormattedValue {
+                value,
+                conversion,
+                format_spec,
+            } => {
+                string.push('{');
+                // The conversion is stored as the character code of the `!s`, `!r` or `!a`
+                if let Some(c) = u8::try_from(*conversion).ok().filter(|c| *c != 0) {
+                    string.push('!');
+                    string.push(char::from(c));
+                }
+                if let Some(format_spec) = format_spec {
+                    let ExprKind::JoinedStr { values } = &format_spec.node else {
+                        return None;
+                    };
+                    string.push(':');
+                    for value in values {
+                        let ExprKind::Constant {
+                            value: Constant::Str(s),
+                            ..
+                        } = &value.node
+                        else {
+                            return None;
+                        };
+                        string.push_str(s);
+                    }
+                }
+                string.push('}');
+                args.push((**value).clone());
+            }
+            // Implicitly concatenated f-strings can come as f-strings nested in the outer one
+            ExprKind::JoinedStr { .. } => {
+                let (nested, nested_args) = nested_fstring(value)?;
+                string.push_str(&nested);
+                args.extend(nested_args);
+            }
+            _ => return None,
+        }
+    }
+    Some((string, args))
+}
+
 /// Parse str.format() AST
 ///
 /// First we need to map all the args and keyword args that exist; then we need to figure
 /// out which order they appear in, in the string itself.
+/// `trailing_args` is whether arguments already followed the message.
 pub fn fix_format_call(
     func: &Expr,
     args: &[Expr],
     keywords: &[Keyword],
     quote: char,
+    config: &Config,
+    trailing_args: bool,
 ) -> Result<Option<(String, Vec<String>)>> {
-    // Get all arguments and named arguments from the str.format(...) call
-    let (f_args, f_named_args) = get_args_and_keywords(args, keywords, quote)?;
-
-    // Copy the string from the str.format() call
+    // Copy the string from the str.format() call. Anything else, like a bytes
+    // target in `b"{}".format(x)`, isn't something we can convert. Implicitly concatenated
+    // targets, like `("{} " "{}").format(a, b)`, are joined into one constant by the parser,
+    // so placeholders are counted across all of their parts.
     let mut string = String::new();
     if let ExprKind::Attribute { value, .. } = &func.node {
         if let ExprKind::Constant {
@@ -194,25 +415,57 @@ pub fn fix_format_call(
         } = &value.node
         {
             string.push_str(s);
+        } else {
+            return Ok(None);
         }
     }
-    // Make a copy of the string for later
-    let mut new_string = string.clone();
+
+    // Manually numbered placeholders are filled in order, like automatically numbered ones
+    let (string, args) = match numbering(config, &string) {
+        Numbering::Automatic => (string, args.to_vec()),
+        Numbering::Manual => match renumber(config, &string, args) {
+            Some(renumbered) => renumbered,
+            None => return Ok(None),
+        },
+        // Python refuses to format these, so there's nothing to convert
+        Numbering::Mixed => return Ok(None),
+    };
+
+    // Nested str.format() calls become part of the message
+    let (mut string, args) = splice_nested_format_calls(config, &string, &args, keywords);
+
+    // Get all arguments and named arguments from the str.format(...) call
+    let (f_args, f_named_args) = get_args_and_keywords(&args, keywords, quote, config)?;
+    // Make a copy of the string for later. Once there are arguments, the logger
+    // formats the message, so a literal `%` needs escaping.
+    let percent_escape = if !config.formatted_value_regex.is_match(&string) {
+        PercentEscape::Keep
+    } else if trailing_args {
+        PercentEscape::Literal
+    } else {
+        PercentEscape::All
+    };
+    // Format specs, like the one in `{:%Y-%m-%d}`, aren't part of the text
+    let escape = |text: &str| escape_percent(&config.printf_conversion_regex, text, percent_escape);
+    let mut new_string = String::with_capacity(string.len());
+    let mut text_start = 0;
+    for placeholder in config.formatted_value_regex.find_iter(&string) {
+        new_string.push_str(&escape(&string[text_start..placeholder.start()]));
+        new_string.push_str(placeholder.as_str());
+        text_start = placeholder.end();
+    }
+    new_string.push_str(&escape(&string[text_start..]));
 
     // Initialize an empty vector which will hold our arguments
     // The str.format() syntax is a little trickier to handle than f-strings, since the
     // call can contain both named an unnamed arguments, and they while the unnamed arguments
     // are inserted in an ordered manner, the named arguments could belong to any of the
     // curly brace pairs. A named argument can also appear multiple times.
-    let mut ordered_arguments: Vec<Option<String>> = vec![
-        None;
-        Regex::new(FORMATTED_VALUE_REGEX)
-            .unwrap()
-            .find_iter(&string)
-            .count()
-    ];
+    let mut ordered_arguments: Vec<Option<String>> =
+        vec![None; config.formatted_value_regex.find_iter(&string).count()];
 
     order(
+        config,
         &mut string,
         &mut new_string,
         f_args,
@@ -220,10 +473,37 @@ pub fn fix_format_call(
         &mut ordered_arguments,
     )?;
 
-    let string_addon = ordered_arguments
-        .iter()
-        .map(|s| s.clone().unwrap())
-        .collect();
+    // loguru formats the message like str.format() did, so it keeps its placeholders, only
+    // without field names, since the arguments are now passed in order
+    let stdlib = config.logger_flavor == LoggerFlavor::Stdlib;
+    if !stdlib {
+        new_string = config
+            .formatted_value_group_regex
+            .replace_all(&string, |cap: &Captures| {
+                placeholder(config, config.logger_flavor, cap)
+            })
+            .into_owned();
+    }
+
+    // Placeholders without an argument, like `{x}` without `x=`, would fail to format anyway
+    let Some(string_addon) = ordered_arguments.into_iter().collect::<Option<Vec<_>>>() else {
+        let filename = THREAD_LOCAL_STATE.with(|tl| tl.filename.clone());
+        emit_skip(
+            config,
+            &format!(
+                "Skipping the logger in `{}` line {}, since a placeholder in its message has no argument",
+                filename,
+                func.location.row()
+            ),
+        );
+        return Ok(None);
+    };
+
+    for cap in config.formatted_value_group_regex.captures_iter(&string) {
+        if let Some(spec) = cap.get(3).filter(|_| stdlib) {
+            warn_centered_spec(config, spec.as_str(), func.location.row());
+        }
+    }
 
     Ok(Some((new_string, string_addon)))
 }
@@ -1,16 +1,19 @@
-use crate::cli::emit_error;
-use crate::parse_format::get_args_and_keywords;
-use crate::visitor::{constant_to_string, operator_to_string};
+use crate::cli::{emit_error, emit_skip, warn_centered_spec, LoggerFlavor};
+use crate::config::Config;
+use crate::visitor::{
+    constant_to_argument, constant_to_string, escape_percent, operator_to_string, source_between,
+    source_segment, unary_operator_to_string, PercentEscape,
+};
 use crate::THREAD_LOCAL_STATE;
 use anyhow::bail;
 use anyhow::Result;
-use rustpython_parser::ast::{Expr, ExprKind};
+use rustpython_parser::ast::{Comprehension, Constant, Expr, ExprKind, Operator, Unaryop};
 
 pub fn parse_formatted_value(
     value: &Expr,
     postfix: String,
-    in_call: bool,
     quote: char,
+    config: &Config,
 ) -> Result<String> {
     let string = match &value.node {
         // When we see a Name node we're typically handling a variable.
@@ -28,20 +31,23 @@ pub fn parse_formatted_value(
         // to reconstruct the entire chain of attributes + names in the end.
         ExprKind::Attribute { value, attr, .. } => {
             if postfix.is_empty() {
-                parse_formatted_value(value, attr.to_string(), false, quote)?
+                parse_formatted_value(value, attr.to_string(), quote, config)?
             } else {
-                parse_formatted_value(value, format!("{attr}.{postfix}"), false, quote)?
+                parse_formatted_value(value, format!("{attr}.{postfix}"), quote, config)?
             }
         }
         // A constant is a value like 1 or None.
         // We want these values to be moved out of the string.
-        ExprKind::Constant { value, .. } => {
-            if in_call {
-                format!("{}{}{}", quote, constant_to_string(value.clone()), quote)
-            } else {
-                constant_to_string(value.clone())
+        // Strings and bytes need to keep their quotes once they're no longer inside one.
+        ExprKind::Constant { value, .. } => match value {
+            // `1.real` would be read as a float, so numbers need parentheses for attributes
+            Constant::Int(_) | Constant::Float(_) | Constant::Complex { .. }
+                if !postfix.is_empty() =>
+            {
+                format!("({}).{postfix}", constant_to_string(value.clone()))
             }
-        }
+            _ => with_postfix(constant_to_argument(value, quote), postfix),
+        },
         // Calls are function calls. So for example we might see f"{len(foo)}" in an f-string.
         // Here, we want to move the entire contents of the formatted value out of the string.
         // This requires us to reconstruct the string from AST.
@@ -50,196 +56,509 @@ pub fn parse_formatted_value(
             args: call_args,
             keywords,
         } => {
-            let (f_args, f_named_args) = get_args_and_keywords(call_args, keywords, quote)?;
-            match &func.node {
-                ExprKind::Name { id, .. } => {
-                    // Create a string with `x=y` for all named arguments and prefix it
-                    // with a comma unless the string ends up being empty.
-                    let mut comma_delimited_named_arguments = f_named_args
-                        .into_iter()
-                        .map(|arg| format!("{}={}", arg.key, constant_to_string(arg.value)))
-                        .collect::<Vec<String>>()
-                        .join(", ");
-                    if !comma_delimited_named_arguments.is_empty() {
-                        comma_delimited_named_arguments =
-                            String::new() + &comma_delimited_named_arguments;
-                    }
+            let mut arguments = call_args
+                .iter()
+                .map(|arg| parse_formatted_value(arg, String::new(), quote, config))
+                .collect::<Result<Vec<_>>>()?;
 
-                    // Finally, push the reconstructed function call to the outside of the string
-                    // and just add a %s in the string.
-                    if !f_args.is_empty() && !comma_delimited_named_arguments.is_empty() {
-                        format!(
-                            "{}({}, {})",
-                            id,
-                            f_args.join(", "),
-                            comma_delimited_named_arguments
-                        )
-                    } else {
-                        format!(
-                            "{}({}{})",
-                            id,
-                            f_args.join(", "),
-                            comma_delimited_named_arguments
-                        )
+            // str.join takes any iterable, so a generator argument doesn't need to become a list
+            if let (ExprKind::Attribute { attr, .. }, [arg]) = (&func.node, &call_args[..]) {
+                if let ExprKind::GeneratorExp { elt, generators } = &arg.node {
+                    if attr == "join" && keywords.is_empty() {
+                        arguments[0] = parse_comprehension(elt, generators, quote, config)?;
                     }
                 }
-                ExprKind::Attribute { value, attr, .. } => {
-                    let call = {
-                        let mut s = "(".to_string();
-                        let mut first_arg = true;
-
-                        for arg in f_args {
-                            if first_arg {
-                                s.push_str(&arg.to_string());
-                                first_arg = false;
-                            } else {
-                                s.push_str(&format!(", {arg}"));
-                            }
-                        }
-                        for kwarg in f_named_args {
-                            if first_arg {
-                                s.push_str(&format!(
-                                    "{}={}",
-                                    kwarg.key,
-                                    constant_to_string(kwarg.value)
-                                ));
-                                first_arg = false;
-                            } else {
-                                s.push_str(&format!(
-                                    ", {}={}",
-                                    kwarg.key,
-                                    constant_to_string(kwarg.value)
-                                ));
-                            }
-                        }
-                        s.push(')');
-                        s
-                    };
-
-                    format!(
-                        "{}.{}{}",
-                        parse_formatted_value(value, postfix, true, quote)?,
-                        attr,
-                        call
-                    )
-                }
-                _ => {
-                    let filename = THREAD_LOCAL_STATE.with(|tl| tl.filename.clone());
-                    emit_error(&format!(
-                        "Failed to parse `{}` line {}",
-                        filename,
-                        func.location.row()
-                    ));
-                    bail!("")
-                }
             }
+
+            // Keyword arguments follow the positional ones, like in `get(url, timeout=5)`
+            for keyword in keywords {
+                let value =
+                    parse_formatted_value(&keyword.node.value, String::new(), quote, config)?;
+                arguments.push(match &keyword.node.arg {
+                    Some(arg) => format!("{arg}={value}"),
+                    // A missing name means the value is unpacked, like `**kwargs`
+                    None => format!("**{value}"),
+                });
+            }
+
+            // The called expression can be anything from a name, like `len`, to an
+            // interleaving of attributes, subscripts and other calls, like `a.b[c]()`
+            let call = format!(
+                "{}({})",
+                parse_primary(func, quote, config)?,
+                arguments.join(", ")
+            );
+
+            // Attributes accessed on the result, like `get_conf().section`, follow the call
+            with_postfix(call, postfix)
         }
         ExprKind::BinOp { left, op, right } => {
-            format!(
-                "{} {} {}",
-                parse_formatted_value(left, postfix.clone(), false, quote)?,
-                operator_to_string(op),
-                parse_formatted_value(right, postfix, false, quote)?
-            )
+            // Keep the spacing around the operator as it was written, like in `a+b`
+            let operator = operator_to_string(op);
+            let operator = left
+                .end_location
+                .and_then(|end| source_between(end, right.location))
+                .filter(|between| between.trim() == operator)
+                .unwrap_or_else(|| format!(" {operator} "));
+            let expression = format!(
+                "{}{}{}",
+                parse_operand(left, op, false, quote, config)?,
+                operator,
+                parse_operand(right, op, true, quote, config)?
+            );
+            with_postfix_parenthesized(expression, postfix)
         }
-        ExprKind::Subscript { value, slice, .. } => {
-            format!(
-                "{}[{}{}{}]",
-                parse_formatted_value(value, postfix.clone(), false, quote)?,
-                quote,
-                parse_formatted_value(slice, postfix, false, quote)?,
-                quote
-            )
+        // Unary operations, like the `-1` in `data[-1]`
+        ExprKind::UnaryOp { op, operand } => {
+            let operand_string = parse_formatted_value(operand, String::new(), quote, config)?;
+            // `-a ** 2` is already `-(a ** 2)`, and `not` binds looser than any operator
+            let parenthesized = match &operand.node {
+                ExprKind::IfExp { .. } => true,
+                ExprKind::BinOp { op: operand_op, .. } => {
+                    *operand_op != Operator::Pow && *op != Unaryop::Not
+                }
+                _ => false,
+            };
+            let expression = if parenthesized {
+                format!("{}({operand_string})", unary_operator_to_string(op))
+            } else {
+                format!("{}{operand_string}", unary_operator_to_string(op))
+            };
+            with_postfix_parenthesized(expression, postfix)
         }
-        ExprKind::ListComp { elt, generators } | ExprKind::GeneratorExp { elt, generators } => {
-            let mut s = format!(
-                "[{}",
-                parse_formatted_value(elt, postfix.clone(), true, quote)?,
+        // Conditional expressions, like `'✓' if exists else '✗'`
+        ExprKind::IfExp { test, body, orelse } => {
+            let expression = format!(
+                "{} if {} else {}",
+                parse_conditional_part(body, quote, config)?,
+                parse_conditional_part(test, quote, config)?,
+                parse_conditional_part(orelse, quote, config)?
             );
-            for generator in generators {
-                s.push_str(&format!(
-                    " for {} in {}",
-                    parse_formatted_value(&generator.target, postfix.clone(), true, quote)?,
-                    parse_formatted_value(&generator.iter, postfix.clone(), true, quote)?
-                ));
+            with_postfix_parenthesized(expression, postfix)
+        }
+        ExprKind::Subscript { value, slice, .. } => {
+            // NumPy-style indexes, like `arr[i, j]`, are tuples without the parentheses
+            let slice = if let ExprKind::Tuple { elts, .. } = &slice.node {
+                parse_elements(elts, quote, config)?.join(", ")
+            } else {
+                parse_formatted_value(slice, String::new(), quote, config)?
+            };
+            let subscript = format!("{}[{}]", parse_primary(value, quote, config)?, slice);
+            with_postfix(subscript, postfix)
+        }
+        // Slices only appear in subscripts, like `data[n:-1]` or `data[::2]`
+        ExprKind::Slice { lower, upper, step } => {
+            let bound = |bound: &Option<Box<Expr>>| match bound {
+                Some(bound) => parse_formatted_value(bound, String::new(), quote, config),
+                None => Ok(String::new()),
+            };
+            let mut slice = format!("{}:{}", bound(lower)?, bound(upper)?);
+            if step.is_some() {
+                slice.push(':');
+                slice.push_str(&bound(step)?);
             }
-            s.push(']');
-            s
+            slice
+        }
+        ExprKind::Tuple { elts, .. } => {
+            let elements = parse_elements(elts, quote, config)?;
+            let tuple = if let [element] = &elements[..] {
+                format!("({element},)")
+            } else {
+                format!("({})", elements.join(", "))
+            };
+            with_postfix(tuple, postfix)
+        }
+        ExprKind::ListComp { elt, generators } | ExprKind::GeneratorExp { elt, generators } => {
+            let list = format!("[{}]", parse_comprehension(elt, generators, quote, config)?);
+            with_postfix(list, postfix)
         }
         ExprKind::DictComp {
             key,
             value,
             generators,
         } => {
-            let mut s = format!(
-                "{{{}: {}",
-                parse_formatted_value(key, postfix.clone(), true, quote)?,
-                parse_formatted_value(value, postfix.clone(), true, quote)?,
+            let s = format!(
+                "{{{}: {}{}}}",
+                parse_formatted_value(key, String::new(), quote, config)?,
+                parse_formatted_value(value, String::new(), quote, config)?,
+                parse_generators(generators, quote, config)?
             );
-            for generator in generators {
-                s.push_str(&format!(
-                    " for {} in {}",
-                    parse_formatted_value(&generator.target, postfix.clone(), true, quote)?,
-                    parse_formatted_value(&generator.iter, postfix.clone(), true, quote)?
-                ));
-            }
-            s.push('}');
-            s
+            with_postfix(s, postfix)
         }
-        ExprKind::JoinedStr { .. } => {
-            bail!("Won't handle f-strings inside f-strings")
+        ExprKind::Dict { keys, values } => {
+            let items = keys
+                .iter()
+                .zip(values)
+                .map(|(key, value)| {
+                    let value = parse_formatted_value(value, String::new(), quote, config)?;
+                    Ok(match key {
+                        Some(key) => format!(
+                            "{}: {}",
+                            parse_formatted_value(key, String::new(), quote, config)?,
+                            value
+                        ),
+                        // A missing key means the value is unpacked into the dict, like `{**other}`
+                        None => format!("**{value}"),
+                    })
+                })
+                .collect::<Result<Vec<String>>>()?;
+            with_postfix(format!("{{{}}}", items.join(", ")), postfix)
         }
-        _ => {
-            let filename = THREAD_LOCAL_STATE.with(|tl| tl.filename.clone());
-            emit_error(&format!(
-                "Failed to parse `{}` line {}",
-                filename,
-                value.location.row()
-            ));
-            bail!("");
+        ExprKind::List { elts, .. } => with_postfix(
+            format!("[{}]", parse_elements(elts, quote, config)?.join(", ")),
+            postfix,
+        ),
+        ExprKind::Set { elts } => with_postfix(
+            format!("{{{}}}", parse_elements(elts, quote, config)?.join(", ")),
+            postfix,
+        ),
+        // Unpacked call arguments, like `*args`
+        ExprKind::Starred { value, .. } => {
+            format!(
+                "*{}",
+                parse_formatted_value(value, String::new(), quote, config)?
+            )
         }
+        // F-strings nested in a placeholder without a conversion or spec are spliced into
+        // the message instead, so the ones left, like `f"{f'{x}'!r}"`, can't be converted
+        _ => unsupported(value, config)?,
     };
     Ok(string)
 }
 
+/// Reconstruct an argument moved out of a message.
+///
+/// With `--force-parse-fallback`, arguments we can't reconstruct from the AST
+/// are copied from the source as they are.
+pub fn parse_argument(value: &Expr, quote: char, config: &Config) -> Result<String> {
+    parse_formatted_value(value, String::new(), quote, config).or_else(|error| {
+        if !config.force_parse_fallback {
+            return Err(error);
+        }
+        let Some(source) = source_segment(value) else {
+            return Err(error);
+        };
+        if config.verbose {
+            let filename = THREAD_LOCAL_STATE.with(|tl| tl.filename.clone());
+            emit!(
+                "Copied `{source}` from the source of `{filename}` line {}",
+                value.location.row()
+            );
+        }
+        Ok(source)
+    })
+}
+
+/// Reconstruct an argument that's already been moved out of a message, for warnings about it.
+/// Unlike `parse_argument`, this doesn't report copying it from the source a second time.
+pub fn render_argument(value: &Expr, quote: char, config: &Config) -> Option<String> {
+    parse_formatted_value(value, String::new(), quote, config)
+        .ok()
+        .or_else(|| {
+            config
+                .force_parse_fallback
+                .then(|| source_segment(value))
+                .flatten()
+        })
+}
+
+/// Give up on an expression we don't know how to reconstruct. With `--force-parse-fallback`
+/// the argument it's part of is copied from the source instead, so there's nothing to report.
+fn unsupported(value: &Expr, config: &Config) -> Result<String> {
+    if !config.force_parse_fallback {
+        let filename = THREAD_LOCAL_STATE.with(|tl| tl.filename.clone());
+        emit_skip(
+            config,
+            &format!(
+                "Skipping the logger in `{}` line {}, since it contains an expression that can't be converted",
+                filename,
+                value.location.row()
+            ),
+        );
+    }
+    bail!("")
+}
+
 fn parse_fstring(
     value: &Expr,
     string: &mut String,
     args: &mut Vec<String>,
     quote: char,
+    config: &Config,
+    flavor: LoggerFlavor,
+    percent_escape: PercentEscape,
 ) -> Result<()> {
     match &value.node {
-        // When we see a constant, we can just add it back to our new string directly
+        // Some parser versions leave empty constants between the pieces, which add nothing
+        ExprKind::Constant {
+            value: Constant::Str(s),
+            ..
+        } if s.is_empty() => (),
+        // When we see a constant, we can just add it back to our new string directly.
+        // Loggers only format messages given arguments, so that's when a literal `%`
+        // needs escaping. loguru runs the message through str.format, so literal braces do.
         ExprKind::Constant { value, .. } => {
-            string.push_str(&constant_to_string(value.clone()));
+            let constant = constant_to_string(value.clone());
+            match flavor {
+                LoggerFlavor::Stdlib => string.push_str(&escape_percent(
+                    &config.printf_conversion_regex,
+                    &constant,
+                    percent_escape,
+                )),
+                LoggerFlavor::Loguru => {
+                    string.push_str(&constant.replace('{', "{{").replace('}', "}}"));
+                }
+            }
         }
         // A FormattedValue is the {} in an f-string.
         // Since a formatted value can contain constants, and we want to recursively
         // handle the structure, we'll handle the parsing of the formatted value in
         // a dedicated function.
-        ExprKind::FormattedValue { value, .. } => {
-            string.push_str("%s");
-            args.push(parse_formatted_value(value, String::new(), false, quote)?);
+        ExprKind::FormattedValue {
+            value,
+            conversion,
+            format_spec,
+        } => {
+            // The conversion is stored as the character code of the `!s`, `!r` or `!a`
+            let conversion = u8::try_from(*conversion)
+                .ok()
+                .filter(|c| *c != 0)
+                .map(char::from);
+            // An f-string nested in a placeholder, like `f"a {f'{b} c'} d"`, is formatted
+            // as it is, so its pieces become part of the message. Ones without placeholders
+            // are left alone, since the message might not need arguments once they're spliced.
+            if let ExprKind::JoinedStr { values } = &value.node {
+                if conversion.is_none() && format_spec.is_none() && has_formatted_value(value) {
+                    for value in values {
+                        parse_fstring(value, string, args, quote, config, flavor, percent_escape)?;
+                    }
+                    return Ok(());
+                }
+            }
+            let spec = format_spec_to_string(format_spec);
+            if flavor == LoggerFlavor::Stdlib {
+                warn_centered_spec(config, &spec, value.location.row());
+            }
+            string.push_str(&flavor.placeholder(config, conversion, &spec));
+            args.push(parse_argument(value, quote, config)?);
+        }
+        // Implicitly concatenated f-strings can come as f-strings nested in the outer one
+        ExprKind::JoinedStr { values } => {
+            for value in values {
+                parse_fstring(value, string, args, quote, config, flavor, percent_escape)?;
+            }
         }
         _ => {
             let filename = THREAD_LOCAL_STATE.with(|tl| tl.filename.clone());
-            emit_error(&format!(
-                "Failed to parse `{}` line {}",
-                filename,
-                value.location.row()
-            ));
+            emit_error(
+                config,
+                &format!(
+                    "Failed to parse `{}` line {}",
+                    filename,
+                    value.location.row()
+                ),
+            );
             bail!("");
         }
     }
     Ok(())
 }
 
-pub fn fix_fstring(values: &[Expr], quote: char) -> Option<(String, Vec<String>)> {
+/// Check whether a piece of an f-string, or any f-string nested in it, has a placeholder
+pub(crate) fn has_formatted_value(value: &Expr) -> bool {
+    match &value.node {
+        ExprKind::FormattedValue { .. } => true,
+        ExprKind::JoinedStr { values } => values.iter().any(has_formatted_value),
+        _ => false,
+    }
+}
+
+/// Render the inside of a comprehension, like `b for b in bs`
+fn parse_comprehension(
+    elt: &Expr,
+    generators: &[Comprehension],
+    quote: char,
+    config: &Config,
+) -> Result<String> {
+    Ok(format!(
+        "{}{}",
+        parse_formatted_value(elt, String::new(), quote, config)?,
+        parse_generators(generators, quote, config)?
+    ))
+}
+
+/// Reconstruct the `for` and `if` clauses of a comprehension
+fn parse_generators(generators: &[Comprehension], quote: char, config: &Config) -> Result<String> {
+    let mut s = String::new();
+    for generator in generators {
+        let prefix = if generator.is_async > 0 { " async" } else { "" };
+        s.push_str(&format!(
+            "{prefix} for {} in {}",
+            parse_formatted_value(&generator.target, String::new(), quote, config)?,
+            parse_conditional_part(&generator.iter, quote, config)?
+        ));
+        for condition in &generator.ifs {
+            s.push_str(&format!(
+                " if {}",
+                parse_conditional_part(condition, quote, config)?
+            ));
+        }
+    }
+    Ok(s)
+}
+
+/// Reconstruct a part of a conditional expression, or an iterable or condition of a
+/// comprehension. Conditional expressions and lambdas there need parentheses, or their
+/// `if` and `else` would be read as part of the surrounding expression, like in
+/// `(a if b else c) if d else e`.
+fn parse_conditional_part(value: &Expr, quote: char, config: &Config) -> Result<String> {
+    let part = parse_formatted_value(value, String::new(), quote, config)?;
+    if matches!(value.node, ExprKind::IfExp { .. } | ExprKind::Lambda { .. }) {
+        Ok(format!("({part})"))
+    } else {
+        Ok(part)
+    }
+}
+
+/// Reconstruct an operand of a binary operation, putting back the parentheses it needs.
+///
+/// Conditional expressions and `not` bind looser than any operator, and other operations
+/// need parentheses when they bind looser than `op`, like `a - b` in `(a - b) * c`.
+/// Operations binding as tight as `op` need them on the right, like in `a - (b - c)`,
+/// except for `**`, which groups from the right.
+fn parse_operand(
+    value: &Expr,
+    op: &Operator,
+    right: bool,
+    quote: char,
+    config: &Config,
+) -> Result<String> {
+    let operand = parse_formatted_value(value, String::new(), quote, config)?;
+    let parenthesized = match &value.node {
+        ExprKind::IfExp { .. } => true,
+        ExprKind::UnaryOp { op: unary_op, .. } => {
+            *unary_op == Unaryop::Not || (*op == Operator::Pow && !right)
+        }
+        ExprKind::BinOp { op: operand_op, .. } => {
+            let (precedence, operand_precedence) = (precedence(op), precedence(operand_op));
+            operand_precedence < precedence
+                || (operand_precedence == precedence && right != (*op == Operator::Pow))
+        }
+        _ => false,
+    };
+    if parenthesized {
+        Ok(format!("({operand})"))
+    } else {
+        Ok(operand)
+    }
+}
+
+/// How tightly a binary operator binds, from `|` up to `**`
+fn precedence(op: &Operator) -> u8 {
+    match op {
+        Operator::BitOr => 0,
+        Operator::BitXor => 1,
+        Operator::BitAnd => 2,
+        Operator::LShift | Operator::RShift => 3,
+        Operator::Add | Operator::Sub => 4,
+        Operator::Mult | Operator::MatMult | Operator::Div | Operator::FloorDiv | Operator::Mod => {
+            5
+        }
+        Operator::Pow => 6,
+    }
+}
+
+/// Reconstruct an expression that is called or subscripted, like `(a + b)` in `(a + b)[0]`.
+/// Operations and conditional expressions bind looser, so they need their parentheses back.
+fn parse_primary(value: &Expr, quote: char, config: &Config) -> Result<String> {
+    let primary = parse_formatted_value(value, String::new(), quote, config)?;
+    if matches!(
+        value.node,
+        ExprKind::BinOp { .. } | ExprKind::UnaryOp { .. } | ExprKind::IfExp { .. }
+    ) {
+        Ok(format!("({primary})"))
+    } else {
+        Ok(primary)
+    }
+}
+
+/// Append the attribute chain collected above an expression, if any
+fn with_postfix(expression: String, postfix: String) -> String {
+    if postfix.is_empty() {
+        expression
+    } else {
+        format!("{expression}.{postfix}")
+    }
+}
+
+/// Like `with_postfix`, for expressions that bind looser than attribute access
+fn with_postfix_parenthesized(expression: String, postfix: String) -> String {
+    if postfix.is_empty() {
+        expression
+    } else {
+        format!("({expression}).{postfix}")
+    }
+}
+
+fn parse_elements(elts: &[Expr], quote: char, config: &Config) -> Result<Vec<String>> {
+    elts.iter()
+        .map(|elt| parse_formatted_value(elt, String::new(), quote, config))
+        .collect()
+}
+
+/// Read the format spec of a formatted value, like the `02f` in `{x:02f}`.
+///
+/// Specs with nested placeholders, like `{x:{width}}`, can't be carried over,
+/// so we treat them as empty.
+fn format_spec_to_string(format_spec: &Option<Box<Expr>>) -> String {
+    let Some(format_spec) = format_spec else {
+        return String::new();
+    };
+    let ExprKind::JoinedStr { values } = &format_spec.node else {
+        return String::new();
+    };
+    let mut spec = String::new();
+    for value in values {
+        match &value.node {
+            ExprKind::Constant {
+                value: Constant::Str(s),
+                ..
+            } => spec.push_str(s),
+            _ => return String::new(),
+        }
+    }
+    spec
+}
+
+/// Convert an f-string to a message and its arguments, with placeholders for the given flavor.
+/// `trailing_args` is whether arguments already followed the message.
+pub fn fix_fstring(
+    values: &[Expr],
+    quote: char,
+    config: &Config,
+    flavor: LoggerFlavor,
+    trailing_args: bool,
+) -> Option<(String, Vec<String>)> {
     let mut string = String::new();
     let mut args = vec![];
+    let percent_escape = if !values.iter().any(has_formatted_value) {
+        PercentEscape::Keep
+    } else if trailing_args {
+        PercentEscape::Literal
+    } else {
+        PercentEscape::All
+    };
 
     for value in values {
-        match parse_fstring(value, &mut string, &mut args, quote) {
+        match parse_fstring(
+            value,
+            &mut string,
+            &mut args,
+            quote,
+            config,
+            flavor,
+            percent_escape,
+        ) {
             Ok(_) => (),
             Err(_) => return None,
         }
@@ -0,0 +1,19 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+// Whether the `--progress` line is on screen. It's redrawn in place, without a newline,
+// so other messages need to clear it first to get a line of their own.
+static SHOWN: AtomicBool = AtomicBool::new(false);
+
+/// Draw the `--progress` line, replacing the one before
+pub fn show_progress(line: &str) {
+    eprint!("\r\x1b[K{line}");
+    SHOWN.store(true, Ordering::Relaxed);
+}
+
+/// Clear the `--progress` line, if it's on screen, so another message can take its place.
+/// It's drawn again with the next update.
+pub fn clear_progress() {
+    if SHOWN.swap(false, Ordering::Relaxed) {
+        eprint!("\r\x1b[K");
+    }
+}
@@ -0,0 +1,320 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use crate::cli::OutputFormat;
+use crate::fix_file::byte_offset;
+use crate::Change;
+
+const MESSAGE: &str = "Logger message can be rewritten to use printf-style formatting";
+
+/// Render the changes found for each file in the given output format.
+///
+/// Text output lists one `filename:line:column` per change, checkstyle output
+/// is a complete XML document, and JSON output is an array with an object per change.
+/// Columns are 1-indexed in all of them.
+///
+/// JSON output includes the lines each change spans, before and after it's made, along with
+/// `context` lines on either side. They're taken from `sources`, each file's content from
+/// before it was changed, and left empty for files missing from it.
+pub fn render_report(
+    format: OutputFormat,
+    files: &[(String, Vec<Change>)],
+    sources: &HashMap<String, String>,
+    context: usize,
+) -> String {
+    match format {
+        OutputFormat::Text => text(files),
+        OutputFormat::Checkstyle => checkstyle(files),
+        OutputFormat::Json => json(files, sources, context),
+    }
+}
+
+/// Render each change as `at filename:line`, followed by the old and new message
+/// in backticks, for reviewing conversions
+pub fn render_preview(files: &[(String, Vec<Change>)]) -> String {
+    let mut output = String::new();
+    for (filename, changes) in files {
+        for change in changes {
+            let _ = writeln!(
+                output,
+                "at {filename}:{}, `{}` → `{}`",
+                change.lineno,
+                change.original,
+                change.new_text()
+            );
+        }
+    }
+    output
+}
+
+fn text(files: &[(String, Vec<Change>)]) -> String {
+    let mut output = String::new();
+    for (filename, changes) in files {
+        for change in changes {
+            let _ = writeln!(
+                output,
+                "{filename}:{}:{}: {MESSAGE}",
+                change.lineno,
+                change.col_offset + 1
+            );
+        }
+    }
+    output
+}
+
+fn checkstyle(files: &[(String, Vec<Change>)]) -> String {
+    let mut output = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<checkstyle>\n");
+    for (filename, changes) in files {
+        if changes.is_empty() {
+            continue;
+        }
+        let _ = writeln!(output, "  <file name=\"{}\">", escape_xml(filename));
+        for change in changes {
+            let _ = writeln!(
+                output,
+                "    <error line=\"{}\" column=\"{}\" severity=\"warning\" message=\"{}\" source=\"printf-log-formatter\"/>",
+                change.lineno,
+                change.col_offset + 1,
+                escape_xml(MESSAGE)
+            );
+        }
+        output.push_str("  </file>\n");
+    }
+    output.push_str("</checkstyle>\n");
+    output
+}
+
+fn json(
+    files: &[(String, Vec<Change>)],
+    sources: &HashMap<String, String>,
+    context: usize,
+) -> String {
+    let mut objects = vec![];
+    for (filename, changes) in files {
+        let lines = sources
+            .get(filename)
+            .map_or(vec![], |source| source.split('\n').collect::<Vec<_>>());
+        for change in changes {
+            // A renamed call target can be on a line before the message
+            let first_lineno = change
+                .new_call_target
+                .as_ref()
+                .map_or(change.lineno, |(lineno, ..)| change.lineno.min(*lineno));
+            let start = (first_lineno - 1).min(lines.len());
+            let end = change.end_lineno.clamp(start, lines.len());
+            let source_lines = &lines[start..end];
+            let mut object = String::new();
+            let _ = write!(
+                object,
+                "{{\"file\": {}, \"line\": {}, \"column\": {}, \"end_line\": {}, \"end_column\": {}, ",
+                json_string(filename),
+                change.lineno,
+                change.col_offset + 1,
+                change.end_lineno,
+                change.end_col_offset + 1
+            );
+            let _ = write!(
+                object,
+                "\"original\": {}, \"replacement\": {}, ",
+                json_string(&change.original),
+                json_string(&change.new_text())
+            );
+            let _ = write!(
+                object,
+                "\"context_before\": {}, \"source_lines\": {}, \"replacement_lines\": {}, \"context_after\": {}}}",
+                json_array(&lines[start.saturating_sub(context)..start]),
+                json_array(source_lines),
+                json_array(&replacement_lines(source_lines, first_lineno, change)),
+                json_array(&lines[end..(end + context).min(lines.len())])
+            );
+            objects.push(object);
+        }
+    }
+    if objects.is_empty() {
+        return "[]\n".to_string();
+    }
+    format!("[\n  {}\n]\n", objects.join(",\n  "))
+}
+
+/// The lines a change spans once it's made, before the logger is spread over several lines
+/// to fit `--line-length`. A message spanning several lines ends up on the first of them.
+/// Lines before the message, starting at `first_lineno`, only have their call target renamed.
+fn replacement_lines(source_lines: &[&str], first_lineno: usize, change: &Change) -> Vec<String> {
+    let message_index = change.lineno - first_lineno;
+    let (Some(first), Some(last)) = (source_lines.get(message_index), source_lines.last()) else {
+        return vec![];
+    };
+    let mut lines = source_lines[..message_index]
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>();
+    let mut prefix = first[..byte_offset(first, change.col_offset)].to_string();
+    if let Some((lineno, start, end, name)) = &change.new_call_target {
+        let line = lines.get_mut(lineno - first_lineno).unwrap_or(&mut prefix);
+        let range = byte_offset(line, *start)..byte_offset(line, *end);
+        line.replace_range(range, name);
+    }
+    let suffix = &last[byte_offset(last, change.end_col_offset)..];
+    // Newlines inside the line come from escapes in the new message, and are written escaped
+    lines.push(format!("{prefix}{}{suffix}", change.new_text()).replace('\n', "\\n"));
+    lines
+}
+
+fn json_array<S: AsRef<str>>(values: &[S]) -> String {
+    let values = values
+        .iter()
+        .map(|value| json_string(value.as_ref()))
+        .collect::<Vec<_>>();
+    format!("[{}]", values.join(", "))
+}
+
+/// Quote a string for JSON, escaping quotes, backslashes and control characters
+fn json_string(value: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if u32::from(c) < 0x20 => {
+                let _ = write!(quoted, "\\u{:04x}", u32::from(c));
+            }
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Escape the characters that can't appear as-is in an XML attribute
+fn escape_xml(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use crate::cli::Opts;
+    use crate::config::Config;
+    use crate::fix_file::get_changes;
+
+    use super::*;
+
+    #[test]
+    fn test_checkstyle_report() {
+        let opts = Opts::parse_from(["printf-log-formatter", "--check", "a.py", "b&c.py"]);
+        let config = Config::from_opts(&opts);
+        let files = vec![
+            (
+                "a.py".to_string(),
+                get_changes(
+                    "logger.error(f'{a}')\nlogger.error(f'{b}')",
+                    "a.py",
+                    &config,
+                ),
+            ),
+            (
+                "b&c.py".to_string(),
+                get_changes("if x:\n    logger.error('{}'.format(c))", "b&c.py", &config),
+            ),
+            ("d.py".to_string(), vec![]),
+        ];
+
+        let report = render_report(OutputFormat::Checkstyle, &files, &HashMap::new(), 0);
+        let expected = [
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>",
+            "<checkstyle>",
+            "  <file name=\"a.py\">",
+            &format!("    <error line=\"1\" column=\"14\" severity=\"warning\" message=\"{MESSAGE}\" source=\"printf-log-formatter\"/>"),
+            &format!("    <error line=\"2\" column=\"14\" severity=\"warning\" message=\"{MESSAGE}\" source=\"printf-log-formatter\"/>"),
+            "  </file>",
+            "  <file name=\"b&amp;c.py\">",
+            &format!("    <error line=\"2\" column=\"18\" severity=\"warning\" message=\"{MESSAGE}\" source=\"printf-log-formatter\"/>"),
+            "  </file>",
+            "</checkstyle>",
+            "",
+        ]
+        .join("\n");
+        assert_eq!(report, expected);
+    }
+
+    #[test]
+    fn test_text_report() {
+        let opts = Opts::parse_from(["printf-log-formatter", "--check", "a.py"]);
+        let config = Config::from_opts(&opts);
+        let files = vec![(
+            "a.py".to_string(),
+            get_changes("logger.error(f'{a}')", "a.py", &config),
+        )];
+
+        assert_eq!(
+            render_report(OutputFormat::Text, &files, &HashMap::new(), 0),
+            format!("a.py:1:14: {MESSAGE}\n")
+        );
+    }
+
+    #[test]
+    fn test_json_report() {
+        let opts = Opts::parse_from(["printf-log-formatter", "--check", "a.py"]);
+        let config = Config::from_opts(&opts);
+        let content =
+            "if x:\n    y = \"b\"\n    logger.error(f'{c}'\n        f'{d}', exc_info=True)\nfoo()";
+        let files = vec![("a.py".to_string(), get_changes(content, "a.py", &config))];
+        let sources = HashMap::from([("a.py".to_string(), content.to_string())]);
+
+        let report = render_report(OutputFormat::Json, &files, &sources, 1);
+        let expected = [
+            "[",
+            "  {\"file\": \"a.py\", \"line\": 3, \"column\": 18, \"end_line\": 4, \"end_column\": 15, \"original\": \"f'{c}'\\n        f'{d}'\", \"replacement\": \"'%s%s', c, d\", \"context_before\": [\"    y = \\\"b\\\"\"], \"source_lines\": [\"    logger.error(f'{c}'\", \"        f'{d}', exc_info=True)\"], \"replacement_lines\": [\"    logger.error('%s%s', c, d, exc_info=True)\"], \"context_after\": [\"foo()\"]}",
+            "]",
+            "",
+        ]
+        .join("\n");
+        assert_eq!(report, expected);
+
+        // Without any changes, or the file's content
+        assert_eq!(render_report(OutputFormat::Json, &[], &sources, 1), "[]\n");
+        let report = render_report(OutputFormat::Json, &files, &HashMap::new(), 1);
+        assert!(report.contains("\"context_before\": [], \"source_lines\": [], \"replacement_lines\": [], \"context_after\": []"));
+
+        // A call target renamed on the line before the message
+        let opts = Opts::parse_from([
+            "printf-log-formatter",
+            "--check",
+            "--rename-warn",
+            "-l",
+            "warning",
+            "a.py",
+        ]);
+        let config = Config::from_opts(&opts);
+        let content = "logger.warn(\n    f'{a}',\n)";
+        let files = vec![("a.py".to_string(), get_changes(content, "a.py", &config))];
+        let sources = HashMap::from([("a.py".to_string(), content.to_string())]);
+        let report = render_report(OutputFormat::Json, &files, &sources, 0);
+        assert!(report.contains("\"source_lines\": [\"logger.warn(\", \"    f'{a}',\"], \"replacement_lines\": [\"logger.warning(\", \"    '%s', a,\"]"));
+    }
+
+    #[test]
+    fn test_preview() {
+        let opts = Opts::parse_from(["printf-log-formatter", "--preview", "a.py"]);
+        let config = Config::from_opts(&opts);
+        assert!(config.check);
+        let files = vec![(
+            "a.py".to_string(),
+            get_changes("x = 1\nlogger.error(f'{a} and {b}')", "a.py", &config),
+        )];
+
+        assert_eq!(
+            render_preview(&files),
+            "at a.py:2, `f'{a} and {b}'` → `'%s and %s', a, b`\n"
+        );
+    }
+}
@@ -0,0 +1,111 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use crate::cli::LogLevel;
+
+/// How many loggers were converted and skipped at each level, for `--stats-json`.
+///
+/// Only loggers with something to convert are counted, i.e., ones with an f-string or
+/// `str.format()` message. Loggers are skipped for carrying a skip marker, or for
+/// containing something we can't convert.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Stats {
+    pub levels: BTreeMap<LogLevel, LevelStats>,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct LevelStats {
+    pub converted: usize,
+    pub skipped: usize,
+}
+
+impl Stats {
+    pub(crate) fn record(&mut self, level: LogLevel, converted: bool) {
+        let counts = self.levels.entry(level).or_default();
+        if converted {
+            counts.converted += 1;
+        } else {
+            counts.skipped += 1;
+        }
+    }
+
+    /// Add the counts from another file
+    pub fn merge(&mut self, other: &Stats) {
+        for (level, other_counts) in &other.levels {
+            let counts = self.levels.entry(*level).or_default();
+            counts.converted += other_counts.converted;
+            counts.skipped += other_counts.skipped;
+        }
+    }
+
+    /// Render as a JSON object keyed by level, like `{"error": {"converted": 2, "skipped": 1}}`.
+    /// Levels without any loggers are left out.
+    pub fn to_json(&self) -> String {
+        let mut output = String::from("{");
+        for (i, (level, counts)) in self.levels.iter().enumerate() {
+            if i > 0 {
+                output.push_str(", ");
+            }
+            let _ = write!(
+                output,
+                "\"{}\": {{\"converted\": {}, \"skipped\": {}}}",
+                level.as_str(),
+                counts.converted,
+                counts.skipped
+            );
+        }
+        output.push_str("}\n");
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use crate::cli::Opts;
+    use crate::config::Config;
+    use crate::fix_file::get_changes_with_stats;
+
+    use super::*;
+
+    #[test]
+    fn test_stats() {
+        let opts = Opts::parse_from([
+            "printf-log-formatter",
+            "--log-level",
+            "debug",
+            "--quiet",
+            "a.py",
+        ]);
+        let config = Config::from_opts(&opts);
+        let (_, mut stats) = get_changes_with_stats(
+            &[
+                "logger.debug(f'{a}')",
+                "logger.error(f'{a}')",
+                "logger.error('{}'.format(b))",
+                "logger.error(f'{a}')  # printf: skip",
+                "logger.warning(f'{a or b}')",
+                "logger.exception(f'{a}')",
+                // Nothing to convert
+                "logger.info('plain')",
+            ]
+            .join("\n"),
+            "a.py",
+            &config,
+        );
+        let (_, other) = get_changes_with_stats("logger.debug(f'{b}')", "b.py", &config);
+        stats.merge(&other);
+
+        assert_eq!(
+            stats.to_json(),
+            concat!(
+                "{\"debug\": {\"converted\": 2, \"skipped\": 0}, ",
+                "\"warning\": {\"converted\": 0, \"skipped\": 1}, ",
+                "\"error\": {\"converted\": 2, \"skipped\": 1}, ",
+                "\"exception\": {\"converted\": 1, \"skipped\": 0}}\n"
+            )
+        );
+        assert_eq!(Stats::default().to_json(), "{}\n");
+    }
+}
@@ -0,0 +1,78 @@
+use std::fmt::Write;
+use std::time::Duration;
+
+/// Time spent in each phase of handling files, summed across them, for `--profile`
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Timings {
+    /// Reading files
+    pub read: Duration,
+    /// Parsing them with `parse_program`
+    pub parse: Duration,
+    /// Walking the syntax tree for changes, as in `get_changes`
+    pub detect: Duration,
+    /// Applying the changes to the content
+    pub apply: Duration,
+    /// Writing fixed files
+    pub write: Duration,
+}
+
+impl Timings {
+    /// Add the time spent on another file
+    pub fn merge(&mut self, other: &Timings) {
+        self.read += other.read;
+        self.parse += other.parse;
+        self.detect += other.detect;
+        self.apply += other.apply;
+        self.write += other.write;
+    }
+
+    /// Render one phase per line, in the order they happen, followed by the wall time of the
+    /// whole run. Files are handled concurrently, so the phases can add up to more than that.
+    pub fn render(&self, total: Duration) -> String {
+        let mut output = String::from("Profile:\n");
+        for (label, duration) in [
+            ("read", self.read),
+            ("parse", self.parse),
+            ("detect", self.detect),
+            ("apply", self.apply),
+            ("write", self.write),
+            ("total", total),
+        ] {
+            let millis = duration.as_secs_f64() * 1000.0;
+            let _ = writeln!(output, "  {label:<7}{millis:>10.3} ms");
+        }
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let mut timings = Timings {
+            read: Duration::from_micros(1500),
+            parse: Duration::from_millis(20),
+            ..Timings::default()
+        };
+        timings.merge(&Timings {
+            parse: Duration::from_millis(5),
+            write: Duration::from_secs(2),
+            ..Timings::default()
+        });
+
+        assert_eq!(
+            timings.render(Duration::from_secs(3)),
+            concat!(
+                "Profile:\n",
+                "  read        1.500 ms\n",
+                "  parse      25.000 ms\n",
+                "  detect      0.000 ms\n",
+                "  apply       0.000 ms\n",
+                "  write    2000.000 ms\n",
+                "  total    3000.000 ms\n",
+            )
+        );
+    }
+}
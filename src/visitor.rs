@@ -1,21 +1,60 @@
-use rustpython_parser::ast::{Constant, Expr, ExprKind, Keyword, Operator};
+use std::collections::{HashMap, HashSet};
 
-use crate::cli::{get_quotes, LogLevel};
-use crate::gen_visitor::Visitor;
-use crate::parse_format::fix_format_call;
-use crate::parse_fstring::fix_fstring;
-use crate::{Change, SETTINGS};
+use regex::Regex;
+use rustpython_parser::ast::{
+    Constant, Expr, ExprKind, Keyword, Location, Operator, Stmt, StmtKind, Unaryop,
+};
+
+use crate::cli::{
+    emit_error, emit_skip, get_quotes, is_raw_string, LogLevel, LoggerFlavor, QuoteStyle,
+};
+use crate::config::Config;
+use crate::fix_file::find_comments;
+use crate::gen_visitor::{walk_expr, walk_stmt, Visitor};
+use crate::parse_format::{fix_format_call, numbering, Numbering};
+use crate::parse_fstring::{fix_fstring, has_formatted_value, render_argument};
+use crate::stats::Stats;
+use crate::{Change, THREAD_LOCAL_STATE};
 
 // List of calls we explicitly know are unlikely to be loggers
 // for example, warnings.warn() is relatively common syntax
 // that we don't want to confuse for logger.warn.
 const BLACKLISTED_NAMES: [&str; 2] = ["warnings", "messages"];
 
-pub(crate) struct LoggerVisitor {
+// Trailing comment for opting a logger out of conversion
+const SKIP_MARKER: &str = "# printf: skip";
+
+// Comment for opting a whole file out, within its first `FILE_SKIP_MARKER_LINES` lines
+const FILE_SKIP_MARKER: &str = "# printf-log-formatter: off";
+const FILE_SKIP_MARKER_LINES: usize = 10;
+
+pub(crate) struct LoggerVisitor<'c> {
     pub(crate) changes: Vec<Change>,
+    pub(crate) config: &'c Config,
+    // Rows carrying a skip marker, from `skipped_rows`
+    pub(crate) skipped_rows: HashSet<usize>,
+    // Names the `logging` module is bound to, from `logging_module_names`
+    pub(crate) logging_names: HashSet<String>,
+    // Names logger methods are assigned to, from `logger_method_aliases`
+    pub(crate) logger_aliases: HashMap<String, Expr>,
+    pub(crate) stats: Stats,
 }
 
-impl<'a> Visitor<'a> for LoggerVisitor {
+impl<'a, 'c> Visitor<'a> for LoggerVisitor<'c> {
+    /// Look for assert messages to convert, with `--convert-asserts`
+    fn visit_stmt(&mut self, stmt: &'a Stmt) {
+        if let StmtKind::Assert { msg: Some(msg), .. } = &stmt.node {
+            if self.config.convert_asserts && !self.is_skipped(msg) {
+                self.handle_assert(msg);
+            }
+        }
+        walk_stmt(self, stmt);
+    }
+
+    /// Annotations aren't evaluated with `from __future__ import annotations`, so loggers in them
+    /// are left alone. Everything else, like decorators and default values, is walked.
+    fn visit_annotation(&mut self, _expr: &'a Expr) {}
+
     /// Look for logger calls.
     ///
     /// Initially Here we're only after one type of call:
@@ -36,98 +75,700 @@ impl<'a> Visitor<'a> for LoggerVisitor {
     /// also not a fool-proof solution, as you can import loggers from other files, etc.
     /// Loggers can also be called anything, not just `logger.info`. Many use `log.info`,
     /// `LOG.info`, and more.
+    ///
+    /// Loggers can be nested anywhere in an expression, like in `x and logger.info(...)`,
+    /// `lambda: logger.info(...)` or a comprehension, so everything else is walked.
     fn visit_expr(&mut self, expr: &'a Expr) {
         match &expr.node {
-            ExprKind::Call { func, args, .. } => self.handle_call(func, args),
-            ExprKind::BoolOp { op: _, values } => {
-                for expr in values {
-                    self.visit_expr(expr);
+            ExprKind::Call {
+                func,
+                args,
+                keywords,
+            } => {
+                // Calls on lines with a skip marker are left alone, along with everything in them
+                let skipped = self.is_skipped(expr);
+                let changes_before = self.changes.len();
+                if is_print(func) {
+                    self.handle_print(func, args, keywords, skipped);
+                } else {
+                    self.handle_call(func, args, keywords, skipped);
+                }
+                // The arguments of a converted logger are rewritten as a whole,
+                // so only look for loggers inside other calls
+                if !skipped && self.changes.len() == changes_before {
+                    walk_expr(self, expr);
                 }
             }
-            _ => (),
+            _ => walk_expr(self, expr),
         }
     }
 }
 
-impl LoggerVisitor {
-    fn handle_call(&mut self, func: &Expr, args: &Vec<Expr>) {
+impl<'c> LoggerVisitor<'c> {
+    /// Check whether any line of an expression carries a skip marker
+    fn is_skipped(&self, expr: &Expr) -> bool {
+        let start_row = expr.location.row();
+        let end_row = expr
+            .end_location
+            .map_or(start_row, |location| location.row());
+        (start_row..=end_row).any(|row| self.skipped_rows.contains(&row))
+    }
+
+    fn handle_call(&mut self, func: &Expr, args: &[Expr], keywords: &[Keyword], skipped: bool) {
+        // Logger methods can be called through a name they were assigned to, like `log_error`
+        // after `log_error = logger.error`, unless that's too loose for `--strict-loggers`
+        let alias = match &func.node {
+            ExprKind::Name { id, .. } if !self.config.strict_loggers => {
+                let Some(method) = self.logger_aliases.get(id) else {
+                    return;
+                };
+                Some(method.clone())
+            }
+            _ => None,
+        };
         if let ExprKind::Attribute {
             value,
             attr: call_attr,
             ..
-        } = &func.node
+        } = &alias.as_ref().unwrap_or(func).node
         {
             // Make sure the call made matches a valid log level
-            let Some(log_level) = LogLevel::maybe_from_str(call_attr) else { return };
+            let Some(log_level) = LogLevel::maybe_from_str(call_attr) else {
+                return;
+            };
+
+            // Make sure we're not handling any expressions like `warnings.warn()`.
+            // Loggers can also be created inline, like `logging.getLogger(__name__).error()`,
+            // so we look at the name the receiver starts from.
+            if let Some(root) = root_name(value) {
+                if BLACKLISTED_NAMES.contains(&root) {
+                    return;
+                }
+            }
 
-            // Only handle log levels above the settings value
-            if SETTINGS.get().unwrap().log_level > log_level {
+            // Loggers can be looked up by index, like `LOGGERS["app"].error()`, unless that's
+            // too loose for `--strict-loggers`
+            if self.config.strict_loggers && contains_subscript(value) {
                 return;
             }
 
-            // Make sure we're not handling any expressions like `warnings.warn()`
+            // Some receivers are only loggers at certain levels, like `metrics.error()`
+            if let Some(receiver) = receiver_name(value) {
+                if !self.config.receiver_handles_level(receiver, log_level) {
+                    return;
+                }
+            }
+
+            // Module-level `logging.<level>()` calls are opt-in, also when `logging` is imported
+            // under another name
             if let ExprKind::Name { id, .. } = &value.node {
-                if BLACKLISTED_NAMES.contains(&&**id) {
+                if self.logging_names.contains(id) && !self.config.include_module_logging {
                     return;
                 }
             }
 
-            // Make sure the first argument is an f-string or a str.format() call
-            //
-            // This is mainly done to avoid false positives for similar syntax,
-            // such as `messages.error(self.request, "foo")`, but it does leave us open to
-            // false negatives from things like `logger.error("foo" + f"{bar}").
-            // Doubt it will cause too many issues.
-            if let Some(first_value) = args.get(0) {
-                match &first_value.node {
-                    ExprKind::JoinedStr { values } => {
-                        for expr in args {
-                            if let ExprKind::JoinedStr { .. } = &expr.node {
-                                self.handle_joinedstr(expr, values);
-                            }
+            // Arguments before the message, like a request passed to a wrapper, are left alone
+            let message_args = args
+                .get(self.config.message_arg_index..)
+                .unwrap_or_default();
+            let keyword_message =
+                message_args.is_empty() && args.len() == self.config.message_arg_index;
+
+            // Only handle the log levels selected in the settings
+            if !self.config.handles_level(log_level) {
+                if !skipped {
+                    let message_args = match keywords.first() {
+                        Some(keyword) if keyword_message => {
+                            std::slice::from_ref(&keyword.node.value)
                         }
-                    }
-                    ExprKind::Call {
-                        func,
-                        args,
-                        keywords,
-                    } => {
-                        if let ExprKind::Attribute { attr, .. } = &func.node {
-                            if attr == "format" {
-                                self.handle_str_format_call(first_value, func, args, keywords);
+                        _ => message_args,
+                    };
+                    self.warn_below_threshold(func, message_args);
+                }
+                return;
+            }
+
+            let changes_before = self.changes.len();
+            if keyword_message {
+                self.handle_keyword_message(log_level, keywords, skipped);
+            } else {
+                self.handle_logger(log_level, message_args, skipped);
+            }
+            // `warn` is a deprecated alias of `warning`, but not for aliases of the method
+            let renamed = call_attr == "warn" && alias.is_none() && self.config.rename_warn;
+            if renamed && self.changes.len() > changes_before {
+                self.rename_warn(func);
+            }
+            if self.config.convert_extra && !skipped {
+                self.handle_extra(keywords);
+            }
+        }
+    }
+
+    /// Convert a message passed by keyword, like `logger.error(msg=f"{x}", exc_info=True)`.
+    ///
+    /// The arguments can only follow the message positionally, so the message is made positional,
+    /// as in `logger.error("%s", x, exc_info=True)`. That's only possible when `msg` is the first
+    /// keyword, so the message ends up after any positional arguments and before other keywords.
+    fn handle_keyword_message(&mut self, level: LogLevel, keywords: &[Keyword], skipped: bool) {
+        let Some(keyword) = keywords.first() else {
+            return;
+        };
+        if keyword.node.arg.as_deref() != Some("msg") {
+            return;
+        }
+
+        let changes_before = self.changes.len();
+        self.handle_logger(level, std::slice::from_ref(&keyword.node.value), skipped);
+        if self.changes.len() > changes_before {
+            // Changes are only made for messages with an end location
+            let end = keyword.node.value.end_location.unwrap();
+            let change = self.changes.last_mut().unwrap();
+            change.lineno = keyword.location.row();
+            change.col_offset = keyword.location.column();
+            change.original = source_span(keyword.location, end);
+        }
+    }
+
+    /// Convert a logger's message, and count the logger as converted or skipped at its level
+    fn handle_logger(&mut self, level: LogLevel, args: &[Expr], skipped: bool) {
+        // Loggers with nothing to convert, like ones with a plain string message, aren't counted
+        if !self.has_convertible_message(args) {
+            return;
+        }
+        let changes_before = self.changes.len();
+        if !skipped {
+            self.handle_message(args, args.len() > 1);
+        }
+        self.stats
+            .record(level, self.changes.len() > changes_before);
+    }
+
+    /// Check whether the message is an f-string with placeholders, or a `str.format()` call
+    /// we'd convert, or a conditional expression choosing between them
+    fn has_convertible_message(&self, args: &[Expr]) -> bool {
+        match args.first().map(|arg| &arg.node) {
+            Some(ExprKind::JoinedStr { values }) => {
+                self.config.strip_useless_fstrings || values.iter().any(has_formatted_value)
+            }
+            Some(ExprKind::Call { func, .. }) => {
+                self.converts_str_format_calls()
+                    && matches!(&func.node, ExprKind::Attribute { attr, .. } if attr == "format")
+            }
+            Some(ExprKind::IfExp { body, orelse, .. }) => {
+                self.has_convertible_message(std::slice::from_ref(body))
+                    || self.has_convertible_message(std::slice::from_ref(orelse))
+            }
+            _ => false,
+        }
+    }
+
+    /// `--format-only-normalize` wants to keep str.format() calls. Otherwise they're converted,
+    /// since they're formatted eagerly, also for loguru, which gets its own brace-style placeholders
+    fn converts_str_format_calls(&self) -> bool {
+        !self.config.format_only_normalize
+    }
+
+    /// Convert `print(f"...")` into a logger call, when `--convert-print` is set.
+    ///
+    /// Only single-argument calls are touched, since `print` joins multiple
+    /// arguments and respects keywords like `file=` in ways a logger doesn't.
+    fn handle_print(&mut self, func: &Expr, args: &[Expr], keywords: &[Keyword], skipped: bool) {
+        let Some(level) = self.config.convert_print else {
+            return;
+        };
+        if args.len() != 1 || !keywords.is_empty() {
+            return;
+        }
+        // The call target is rewritten on the message's first line, so they need to share it
+        if func.location.row() != args[0].location.row() {
+            return;
+        }
+        let Some(func_end) = func.end_location else {
+            return;
+        };
+
+        let changes_before = self.changes.len();
+        self.handle_logger(level, args, skipped);
+        if self.changes.len() > changes_before {
+            let change = self.changes.last_mut().unwrap();
+            change.new_call_target = Some((
+                func.location.row(),
+                func.location.column(),
+                func_end.column(),
+                format!("{}.{}", self.config.print_logger_name, level.as_str()),
+            ));
+        }
+    }
+
+    /// Rename the converted `logger.warn()` call to `logger.warning()`, with `--rename-warn`.
+    fn rename_warn(&mut self, func: &Expr) {
+        let Some(func_end) = func.end_location else {
+            return;
+        };
+        let change = self.changes.last_mut().unwrap();
+        change.new_call_target = Some((
+            func_end.row(),
+            func_end.column() - "warn".len(),
+            func_end.column(),
+            "warning".to_string(),
+        ));
+    }
+
+    /// Convert f-strings among the values of a logger's `extra` dict to printf-style `%`
+    /// expressions, like `extra={"detail": f"{x}"}` to `extra={"detail": "%s" % (x,)}`.
+    ///
+    /// The logger doesn't format `extra`, so like assert messages, this is only for consistency.
+    fn handle_extra(&mut self, keywords: &[Keyword]) {
+        let extra = keywords
+            .iter()
+            .find(|keyword| keyword.node.arg.as_deref() == Some("extra"));
+        let Some(extra) = extra else { return };
+        let ExprKind::Dict { values, .. } = &extra.node.value.node else {
+            return;
+        };
+
+        let config = self.config;
+        for value in values {
+            let ExprKind::JoinedStr { values: parts } = &value.node else {
+                continue;
+            };
+            // Without any arguments, there's nothing to move out of the string
+            if !parts.iter().any(has_formatted_value) {
+                continue;
+            }
+            let changes_before = self.changes.len();
+            // Every `%` in the f-string was literal, so they're all escaped for the `%` operator
+            self.capture_changes(value, parts, std::iter::empty(), |parts, quote| {
+                fix_fstring(parts, quote, config, LoggerFlavor::Stdlib, false)
+            });
+            if self.changes.len() > changes_before {
+                self.changes.last_mut().unwrap().percent_format = true;
+            }
+        }
+    }
+
+    /// Convert an f-string assert message to a `str.format()` call, like
+    /// `assert x, f"{x!r} is wrong"` to `assert x, "{!r} is wrong".format(x)`.
+    ///
+    /// Asserts can't take printf-style arguments, so this is only for consistency.
+    /// Unlike with loggers, the message is still formatted as soon as the assert fails.
+    fn handle_assert(&mut self, msg: &Expr) {
+        let ExprKind::JoinedStr { values } = &msg.node else {
+            return;
+        };
+        // Without any arguments, there's nothing to move out of the message
+        if !values.iter().any(has_formatted_value) {
+            return;
+        }
+
+        let config = self.config;
+        let changes_before = self.changes.len();
+        // str.format() takes the same placeholders as loguru
+        self.capture_changes(msg, values, std::iter::empty(), |values, quote| {
+            fix_fstring(values, quote, config, LoggerFlavor::Loguru, false)
+        });
+        if self.changes.len() > changes_before {
+            self.changes.last_mut().unwrap().str_format = true;
+        }
+    }
+
+    fn handle_message(&mut self, args: &[Expr], trailing_args: bool) {
+        // Make sure the first argument is an f-string or a str.format() call
+        //
+        // This is mainly done to avoid false positives for similar syntax,
+        // such as `messages.error(self.request, "foo")`, but it does leave us open to
+        // false negatives from things like `logger.error("foo" + f"{bar}").
+        // Doubt it will cause too many issues.
+        if let Some(first_value) = args.get(0) {
+            match &first_value.node {
+                // Only the message is converted; f-strings after it are values for its placeholders
+                ExprKind::JoinedStr { values } => {
+                    self.handle_joinedstr(first_value, values, trailing_args);
+                }
+                ExprKind::Call {
+                    func,
+                    args,
+                    keywords,
+                } if self.converts_str_format_calls() => {
+                    if let ExprKind::Attribute { value, attr, .. } = &func.node {
+                        if attr == "format" {
+                            match &value.node {
+                                ExprKind::JoinedStr { .. } => {
+                                    self.warn_formatted_fstring(first_value);
+                                }
+                                // Bytes have no .format(), so there's nothing to convert
+                                ExprKind::Constant {
+                                    value: Constant::Bytes(_),
+                                    ..
+                                } => (),
+                                _ => {
+                                    self.handle_str_format_call(
+                                        first_value,
+                                        func,
+                                        args,
+                                        keywords,
+                                        trailing_args,
+                                    );
+                                }
                             }
                         }
                     }
-                    _ => (),
                 }
+                // A choice between two messages, like `f"{x}" if verbose else "{}".format(x)`
+                ExprKind::IfExp { body, orelse, .. } => {
+                    self.handle_message_choice(first_value, body, orelse, trailing_args);
+                }
+                _ => (),
+            }
+        }
+    }
+
+    /// Convert both messages of a conditional expression, like `f"{x}!" if loud else f"{x}"`.
+    ///
+    /// The arguments can only follow the whole expression, so both messages need to end up
+    /// with the same ones, in the same order. Otherwise the logger is left alone.
+    /// `str.format()` calls carry their own arguments, so with `--format-only-normalize`
+    /// each message is converted on its own.
+    fn handle_message_choice(
+        &mut self,
+        expr: &Expr,
+        body: &Expr,
+        orelse: &Expr,
+        trailing_args: bool,
+    ) {
+        // The arguments are written after the second message, so it has to end the
+        // expression, and not be wrapped in parentheses of its own
+        if orelse.end_location != expr.end_location {
+            return;
+        }
+        let changes_before = self.changes.len();
+        self.handle_message(std::slice::from_ref(body), trailing_args);
+        self.handle_message(std::slice::from_ref(orelse), trailing_args);
+        if self.config.format_only_normalize {
+            return;
+        }
+
+        let changes = &mut self.changes[changes_before..];
+        if let [body_change, orelse_change] = changes {
+            if body_change.new_string_variables == orelse_change.new_string_variables {
+                body_change.new_string_variables.clear();
+                return;
             }
         }
+        if self.changes.len() > changes_before {
+            let filename = THREAD_LOCAL_STATE.with(|tl| tl.filename.clone());
+            emit_skip(
+                self.config,
+                &format!(
+                    "Skipping the logger in `{filename}` line {}, since both messages need the same arguments",
+                    expr.location.row()
+                ),
+            );
+        }
+        self.changes.truncate(changes_before);
     }
 
-    fn capture_changes<F>(&mut self, expr: &Expr, values: &[Expr], conversion_fn: F)
-    where
+    /// Record the change for a logger message. `arguments` are the expressions
+    /// moved out of the message, for spotting the ones that are expensive to evaluate.
+    fn capture_changes<'e, F>(
+        &mut self,
+        expr: &Expr,
+        values: &[Expr],
+        arguments: impl Iterator<Item = &'e Expr>,
+        conversion_fn: F,
+    ) where
         F: FnOnce(&[Expr], char) -> Option<(String, Vec<String>)>,
     {
-        let Ok(quote) = get_quotes(expr.location.row(), expr.location.column()) else { return };
+        // The parser leaves the end out for some nodes, and without it we can't tell what to replace
+        let Some(end_location) = expr.end_location else {
+            let filename = THREAD_LOCAL_STATE.with(|tl| tl.filename.clone());
+            emit_error(
+                self.config,
+                &format!(
+                    "Failed to find the end of the logger message in `{filename}` line {}",
+                    expr.location.row()
+                ),
+            );
+            return;
+        };
+        let Ok(inferred_quote) =
+            get_quotes(self.config, expr.location.row(), expr.location.column())
+        else {
+            return;
+        };
+        let quote = self.config.quote.map_or(inferred_quote, QuoteStyle::char);
 
         if let Some((new_string_content, new_string_variables)) = conversion_fn(values, quote) {
             if !new_string_content.is_empty() {
+                let quote = self.pick_quote(quote, &new_string_content, expr);
+                let arguments = arguments.collect::<Vec<_>>();
+                let render = |argument: &&Expr| render_argument(argument, quote, self.config);
+                let eager_args = arguments
+                    .iter()
+                    .filter(|argument| contains_call(argument))
+                    .filter_map(render)
+                    .collect::<Vec<_>>();
+                let side_effect_args = arguments
+                    .iter()
+                    .filter(|argument| calls_any(argument, &self.config.warn_side_effects))
+                    .filter_map(render)
+                    .collect::<Vec<_>>();
+                // Placeholders reusing one of `.format()`'s arguments, like `{a} {a}`, are fine
+                let duplicate_args =
+                    duplicates(&arguments.iter().filter_map(render).collect::<Vec<_>>());
+                // Raw messages, like regex patterns, stay raw when they can
+                let raw = is_raw_string(expr.location.row(), expr.location.column())
+                    && is_raw_safe(&new_string_content, quote);
+                self.warn_eager_args(expr, &eager_args);
+                self.warn_side_effects(expr, &side_effect_args);
+                self.warn_duplicate_args(expr, &duplicate_args);
                 self.changes.push(Change {
+                    filename: THREAD_LOCAL_STATE.with(|tl| tl.filename.clone()),
                     lineno: expr.location.row(),
                     col_offset: expr.location.column(),
-                    end_lineno: expr.end_location.unwrap().row(),
-                    end_col_offset: expr.end_location.unwrap().column(),
-                    new_string_content,
+                    end_lineno: end_location.row(),
+                    end_col_offset: end_location.column(),
+                    original: source_span(expr.location, end_location),
+                    new_string_content: if raw {
+                        new_string_content
+                    } else {
+                        escape_quotes(&new_string_content, quote)
+                    },
+                    raw,
                     new_string_variables,
                     quote,
+                    new_call_target: None,
+                    str_format: false,
+                    percent_format: false,
+                    eager_args,
+                    side_effect_args,
+                    duplicate_args,
                 });
             }
         }
     }
 
+    /// Forcing a quote style on a message full of that quote character means
+    /// escaping every one of them. When the other quote character wouldn't need
+    /// any escaping, we use that one for this logger instead.
+    fn pick_quote(&self, quote: char, content: &str, expr: &Expr) -> char {
+        if self.config.quote.is_none() {
+            return quote;
+        }
+        let other_quote = if quote == '"' { '\'' } else { '"' };
+        if content.matches(quote).count() > 1 && !content.contains(other_quote) {
+            if self.config.verbose {
+                let filename = THREAD_LOCAL_STATE.with(|tl| tl.filename.clone());
+                emit!(
+                    "Using {other_quote} quotes for the logger in `{filename}` line {} to avoid escaping",
+                    expr.location.row()
+                );
+            }
+            return other_quote;
+        }
+        quote
+    }
+
+    /// Arguments moved out of a message are still evaluated when the logger is disabled,
+    /// so with `--warn-eager-args` we point out the ones involving calls.
+    fn warn_eager_args(&self, expr: &Expr, eager_args: &[String]) {
+        if !self.config.warn_eager_args || self.config.quiet {
+            return;
+        }
+        let filename = THREAD_LOCAL_STATE.with(|tl| tl.filename.clone());
+        for argument in eager_args {
+            emit!(
+                "`{argument}` in `{filename}` line {} is evaluated even when the logger is disabled",
+                expr.location.row()
+            );
+        }
+    }
+
+    /// Arguments used to be evaluated while formatting the message, but are now evaluated
+    /// before the call. With `--warn-side-effects`, we point out the ones calling any of
+    /// the given names, since the point their side effects happen at has moved.
+    fn warn_side_effects(&self, expr: &Expr, side_effect_args: &[String]) {
+        if self.config.quiet {
+            return;
+        }
+        let filename = THREAD_LOCAL_STATE.with(|tl| tl.filename.clone());
+        for argument in side_effect_args {
+            emit!(
+                "`{argument}` in `{filename}` line {} may have side effects, and is now evaluated before the logger call",
+                expr.location.row()
+            );
+        }
+    }
+
+    /// Loggers below the log level, or at a level not given to `--levels`, are left alone, so
+    /// their f-strings are still formatted even when the logger is disabled. With
+    /// `--warn-below-threshold`, we point them out.
+    fn warn_below_threshold(&self, func: &Expr, message_args: &[Expr]) {
+        if !self.config.warn_below_threshold
+            || self.config.quiet
+            || !self.has_convertible_message(message_args)
+        {
+            return;
+        }
+        let Some(logger) = source_segment(func) else {
+            return;
+        };
+        let filename = THREAD_LOCAL_STATE.with(|tl| tl.filename.clone());
+        let reason = if self.config.levels.is_empty() {
+            "is below the log level"
+        } else {
+            "isn't at one of the `--levels` handled"
+        };
+        emit!(
+            "`{logger}` in `{filename}` line {} {reason}, so its message is still formatted eagerly",
+            func.location.row()
+        );
+    }
+
+    /// With `--warn-duplicate-args`, point out arguments moved out of a message more than once.
+    /// Each placeholder needs its own argument in printf-style messages, so this is correct,
+    /// but the same expression in several placeholders is often a mistake in the original.
+    fn warn_duplicate_args(&self, expr: &Expr, duplicate_args: &[String]) {
+        if !self.config.warn_duplicate_args || self.config.quiet {
+            return;
+        }
+        let filename = THREAD_LOCAL_STATE.with(|tl| tl.filename.clone());
+        for argument in duplicate_args {
+            emit!(
+                "`{argument}` is used more than once in the logger message in `{filename}` line {}",
+                expr.location.row()
+            );
+        }
+    }
+
+    /// Calling `.format()` on an f-string, like `f"{prefix} {{}}".format(x)`, runs whatever
+    /// the f-string interpolated through `.format()` too, so any braces in `prefix` would be
+    /// formatted as well. There's no equivalent printf-style message, so we leave these alone.
+    fn warn_formatted_fstring(&self, expr: &Expr) {
+        if !self.config.quiet {
+            let filename = THREAD_LOCAL_STATE.with(|tl| tl.filename.clone());
+            emit!(
+                "Skipping the logger in `{filename}` line {}, since it calls .format() on an f-string",
+                expr.location.row()
+            );
+        }
+    }
+
+    /// Python refuses to format strings mixing automatic and manual numbering, like
+    /// `"{} {0}".format(x)`, so the logger already fails. We leave it alone rather than
+    /// hiding that behind a conversion.
+    fn warn_mixed_numbering(&self, expr: &Expr) {
+        if !self.config.quiet {
+            let filename = THREAD_LOCAL_STATE.with(|tl| tl.filename.clone());
+            emit!(
+                "Skipping the logger in `{filename}` line {}, since its message mixes automatic and manual field numbering",
+                expr.location.row()
+            );
+        }
+    }
+
+    /// With `--respect-existing-percent`, messages already containing printf-style placeholders
+    /// are left alone, since their arguments are easy to get out of step with the ones we add.
+    fn respects_existing_percent(&self, expr: &Expr, text: &str) -> bool {
+        if !self.config.respect_existing_percent
+            || self.config.logger_flavor != LoggerFlavor::Stdlib
+            || percent_conversions(&self.config.printf_conversion_regex, text).is_empty()
+        {
+            return false;
+        }
+        if !self.config.quiet {
+            let filename = THREAD_LOCAL_STATE.with(|tl| tl.filename.clone());
+            emit!(
+                "Skipping the logger in `{filename}` line {}, since its message already contains printf-style placeholders",
+                expr.location.row()
+            );
+        }
+        true
+    }
+
+    /// Once arguments we move out of a message follow it, they come before the ones that
+    /// already did. Conversions already in the message's text take those, so they all need to
+    /// come after the placeholders we add, and can't be named, like `%(ms)s`, which takes
+    /// a mapping instead. Otherwise the message is left alone.
+    ///
+    /// `text_before` is the message's text up to its last placeholder.
+    fn misplaces_existing_percent(&self, expr: &Expr, text_before: &str, text: &str) -> bool {
+        let re = &self.config.printf_conversion_regex;
+        if self.config.logger_flavor != LoggerFlavor::Stdlib
+            || (percent_conversions(re, text_before).is_empty()
+                && !percent_conversions(re, text)
+                    .iter()
+                    .any(|conversion| conversion.starts_with("%(")))
+        {
+            return false;
+        }
+        let filename = THREAD_LOCAL_STATE.with(|tl| tl.filename.clone());
+        emit_skip(
+            self.config,
+            &format!(
+                "Skipping the logger in `{filename}` line {}, since the printf-style placeholders already in its message can't be kept in step with its arguments",
+                expr.location.row()
+            ),
+        );
+        true
+    }
+
     /// Handle f-string AST node
-    fn handle_joinedstr(&mut self, expr: &Expr, values: &[Expr]) {
-        self.capture_changes(expr, values, fix_fstring);
+    fn handle_joinedstr(&mut self, expr: &Expr, values: &[Expr], trailing_args: bool) {
+        // Without placeholders, like `f"just text"`, there's nothing to gain from a rewrite,
+        // unless the `f` is to be dropped with `--strip-useless-fstrings`
+        let has_placeholders = values.iter().any(has_formatted_value);
+        if !has_placeholders && !self.config.strip_useless_fstrings {
+            return;
+        }
+        let text_of = |values: &[Expr]| {
+            values
+                .iter()
+                .filter_map(|value| match &value.node {
+                    ExprKind::Constant {
+                        value: Constant::Str(s),
+                        ..
+                    } => Some(s.as_str()),
+                    _ => None,
+                })
+                .collect::<String>()
+        };
+        let text = text_of(values);
+        if self.respects_existing_percent(expr, &text) {
+            return;
+        }
+        let last_placeholder = values.iter().rposition(has_formatted_value).unwrap_or(0);
+        if has_placeholders
+            && trailing_args
+            && self.misplaces_existing_percent(expr, &text_of(&values[..last_placeholder]), &text)
+        {
+            return;
+        }
+        let config = self.config;
+        let arguments = values.iter().filter_map(|value| match &value.node {
+            ExprKind::FormattedValue { value, .. } => Some(&**value),
+            _ => None,
+        });
+        // With `--format-only-normalize`, the message becomes a `str.format()` call,
+        // which takes the same placeholders as loguru. Messages without placeholders
+        // aren't formatted at all, so their text is kept as it is.
+        let flavor = if !has_placeholders {
+            LoggerFlavor::Stdlib
+        } else if config.format_only_normalize {
+            LoggerFlavor::Loguru
+        } else {
+            config.logger_flavor
+        };
+        let changes_before = self.changes.len();
+        self.capture_changes(expr, values, arguments, |values, quote| {
+            fix_fstring(values, quote, config, flavor, trailing_args)
+        });
+        if config.format_only_normalize && self.changes.len() > changes_before {
+            let change = self.changes.last_mut().unwrap();
+            // Messages without arguments are plain strings, and don't need the call
+            change.str_format = !change.new_string_variables.is_empty();
+        }
     }
 
     /// Handle str.format() call AST node
@@ -137,19 +778,491 @@ impl LoggerVisitor {
         func: &Expr,
         args: &[Expr],
         keywords: &[Keyword],
+        trailing_args: bool,
     ) {
-        self.capture_changes(first_value, args, |args, quote| {
-            fix_format_call(func, args, keywords, quote).ok().flatten()
+        if let ExprKind::Attribute { value, .. } = &func.node {
+            if let ExprKind::Constant {
+                value: Constant::Str(s),
+                ..
+            } = &value.node
+            {
+                // Format specs, like the one in `{:%Y-%m-%d}`, aren't part of the text
+                let text = self.config.formatted_value_regex.replace_all(s, "");
+                if self.respects_existing_percent(first_value, &text) {
+                    return;
+                }
+                let last_placeholder = self.config.formatted_value_regex.find_iter(s).last();
+                if let (Some(placeholder), true) = (last_placeholder, trailing_args) {
+                    let text_before = &s[..placeholder.start()];
+                    let text_before = self
+                        .config
+                        .formatted_value_regex
+                        .replace_all(text_before, "");
+                    if self.misplaces_existing_percent(first_value, &text_before, &text) {
+                        return;
+                    }
+                }
+                if numbering(self.config, s) == Numbering::Mixed {
+                    self.warn_mixed_numbering(first_value);
+                    return;
+                }
+            }
+        }
+        let config = self.config;
+        let arguments = args
+            .iter()
+            .chain(keywords.iter().map(|keyword| &keyword.node.value));
+        self.capture_changes(first_value, args, arguments, |args, quote| {
+            fix_format_call(func, args, keywords, quote, config, trailing_args)
+                .ok()
+                .flatten()
         });
     }
 }
 
+/// Check whether a file opts out with `# printf-log-formatter: off` on a line of its own,
+/// near the top. Only the first few lines are searched, so this is cheaper than parsing.
+pub(crate) fn is_file_skipped(content: &str) -> bool {
+    content
+        .split('\n')
+        .take(FILE_SKIP_MARKER_LINES)
+        .any(|line| line.trim() == FILE_SKIP_MARKER)
+}
+
+/// Find the rows carrying a skip marker, i.e., `# printf: skip` or a `# noqa`
+/// comment naming this tool. Markers inside string literals don't count.
+/// Rows are 1-indexed, like the parser's.
+pub(crate) fn skipped_rows(content: &str) -> HashSet<usize> {
+    let mut rows = HashSet::new();
+    let (mut row, mut counted) = (1, 0);
+    for (offset, comment) in find_comments(content) {
+        if comment.contains(SKIP_MARKER)
+            || (comment.contains("# noqa") && comment.contains("printf-log-formatter"))
+        {
+            row += content[counted..offset].matches('\n').count();
+            counted = offset;
+            rows.insert(row);
+        }
+    }
+    rows
+}
+
+/// The source code an expression spans, unless the parser left its end out
+pub fn source_segment(expr: &Expr) -> Option<String> {
+    expr.end_location
+        .map(|end_location| source_span(expr.location, end_location))
+}
+
+/// The source code between two locations
+fn source_span(start: Location, end: Location) -> String {
+    THREAD_LOCAL_STATE.with(|tl| {
+        let lines = tl
+            .content
+            .split('\n')
+            .skip(start.row() - 1)
+            .take(end.row() - start.row() + 1)
+            .collect::<Vec<_>>();
+        if let [line] = lines[..] {
+            return line
+                .chars()
+                .skip(start.column())
+                .take(end.column() - start.column())
+                .collect();
+        }
+        let mut segment = lines[0].chars().skip(start.column()).collect::<String>();
+        for line in &lines[1..lines.len() - 1] {
+            segment.push('\n');
+            segment.push_str(line);
+        }
+        segment.push('\n');
+        segment.extend(lines[lines.len() - 1].chars().take(end.column()));
+        segment
+    })
+}
+
+/// The source code between two locations on the same line
+pub(crate) fn source_between(start: Location, end: Location) -> Option<String> {
+    if start.row() != end.row() || start.column() > end.column() {
+        return None;
+    }
+    THREAD_LOCAL_STATE.with(|tl| {
+        let line = tl.content.split('\n').nth(start.row() - 1)?;
+        Some(
+            line.chars()
+                .skip(start.column())
+                .take(end.column() - start.column())
+                .collect(),
+        )
+    })
+}
+
+/// Check whether evaluating an expression calls anything, like `expensive()` or
+/// `[str(e) for e in errors]`. Comprehensions count, since they loop over their iterable.
+fn contains_call(expr: &Expr) -> bool {
+    match &expr.node {
+        ExprKind::Call { .. }
+        | ExprKind::Await { .. }
+        | ExprKind::ListComp { .. }
+        | ExprKind::SetComp { .. }
+        | ExprKind::DictComp { .. }
+        | ExprKind::GeneratorExp { .. } => true,
+        ExprKind::Attribute { value, .. }
+        | ExprKind::UnaryOp { operand: value, .. }
+        | ExprKind::Starred { value, .. }
+        | ExprKind::FormattedValue { value, .. } => contains_call(value),
+        ExprKind::Subscript { value, slice, .. } => contains_call(value) || contains_call(slice),
+        ExprKind::BinOp { left, right, .. } => contains_call(left) || contains_call(right),
+        ExprKind::Compare {
+            left, comparators, ..
+        } => contains_call(left) || comparators.iter().any(contains_call),
+        ExprKind::IfExp { test, body, orelse } => {
+            contains_call(test) || contains_call(body) || contains_call(orelse)
+        }
+        ExprKind::BoolOp { values, .. } | ExprKind::JoinedStr { values } => {
+            values.iter().any(contains_call)
+        }
+        ExprKind::List { elts, .. } | ExprKind::Tuple { elts, .. } | ExprKind::Set { elts } => {
+            elts.iter().any(contains_call)
+        }
+        ExprKind::Dict { keys, values } => {
+            keys.iter().flatten().any(contains_call) || values.iter().any(contains_call)
+        }
+        _ => false,
+    }
+}
+
+/// Check whether evaluating an expression calls a function or method with one of
+/// the given names, like `queue.pop()` for `pop`
+fn calls_any(expr: &Expr, names: &[String]) -> bool {
+    if names.is_empty() {
+        return false;
+    }
+    let called = |func: &Expr| match &func.node {
+        ExprKind::Name { id, .. } => names.contains(id),
+        ExprKind::Attribute { attr, .. } => names.contains(attr),
+        _ => false,
+    };
+    match &expr.node {
+        ExprKind::Call {
+            func,
+            args,
+            keywords,
+        } => {
+            called(func)
+                || calls_any(func, names)
+                || args.iter().any(|arg| calls_any(arg, names))
+                || keywords.iter().any(|kw| calls_any(&kw.node.value, names))
+        }
+        ExprKind::ListComp { elt, generators }
+        | ExprKind::SetComp { elt, generators }
+        | ExprKind::GeneratorExp { elt, generators } => {
+            calls_any(elt, names)
+                || generators.iter().any(|generator| {
+                    calls_any(&generator.iter, names)
+                        || generator.ifs.iter().any(|test| calls_any(test, names))
+                })
+        }
+        ExprKind::DictComp {
+            key,
+            value,
+            generators,
+        } => {
+            calls_any(key, names)
+                || calls_any(value, names)
+                || generators.iter().any(|generator| {
+                    calls_any(&generator.iter, names)
+                        || generator.ifs.iter().any(|test| calls_any(test, names))
+                })
+        }
+        ExprKind::Attribute { value, .. }
+        | ExprKind::Await { value }
+        | ExprKind::UnaryOp { operand: value, .. }
+        | ExprKind::Starred { value, .. }
+        | ExprKind::FormattedValue { value, .. } => calls_any(value, names),
+        ExprKind::Subscript { value, slice, .. } => {
+            calls_any(value, names) || calls_any(slice, names)
+        }
+        ExprKind::BinOp { left, right, .. } => calls_any(left, names) || calls_any(right, names),
+        ExprKind::Compare {
+            left, comparators, ..
+        } => calls_any(left, names) || comparators.iter().any(|c| calls_any(c, names)),
+        ExprKind::IfExp { test, body, orelse } => {
+            calls_any(test, names) || calls_any(body, names) || calls_any(orelse, names)
+        }
+        ExprKind::BoolOp { values, .. } | ExprKind::JoinedStr { values } => {
+            values.iter().any(|value| calls_any(value, names))
+        }
+        ExprKind::List { elts, .. } | ExprKind::Tuple { elts, .. } | ExprKind::Set { elts } => {
+            elts.iter().any(|elt| calls_any(elt, names))
+        }
+        ExprKind::Dict { keys, values } => {
+            keys.iter().flatten().any(|key| calls_any(key, names))
+                || values.iter().any(|value| calls_any(value, names))
+        }
+        _ => false,
+    }
+}
+
+/// Names the `logging` module is bound to, like `lg` after `import logging as lg`.
+///
+/// Imports anywhere in the file count, including conditional ones inside `if` or `try`.
+/// Loggers created from an aliased module, like `lg.getLogger(__name__)`, don't need this,
+/// since calls on any receiver are handled.
+pub(crate) fn logging_module_names(program: &[Stmt]) -> HashSet<String> {
+    let mut imports = LoggingImports {
+        names: HashSet::from(["logging".to_string()]),
+    };
+    program.iter().for_each(|stmt| imports.visit_stmt(stmt));
+    imports.names
+}
+
+struct LoggingImports {
+    names: HashSet<String>,
+}
+
+impl<'a> Visitor<'a> for LoggingImports {
+    fn visit_stmt(&mut self, stmt: &'a Stmt) {
+        if let StmtKind::Import { names } = &stmt.node {
+            for alias in names.iter().filter(|alias| alias.node.name == "logging") {
+                if let Some(asname) = &alias.node.asname {
+                    self.names.insert(asname.clone());
+                }
+            }
+        }
+        walk_stmt(self, stmt);
+    }
+
+    // Imports are statements, so there's nothing to find in expressions
+    fn visit_expr(&mut self, _expr: &'a Expr) {}
+}
+
+/// Names logger methods are assigned to, like `log_error` after `log_error = logger.error`
+/// or `log_error = functools.partial(logger.error)`, mapped to the method.
+///
+/// Names aren't scoped, so ones assigned more than once anywhere in the file are left out.
+pub(crate) fn logger_method_aliases(program: &[Stmt]) -> HashMap<String, Expr> {
+    let mut assignments = LoggerAssignments {
+        methods: HashMap::new(),
+    };
+    program.iter().for_each(|stmt| assignments.visit_stmt(stmt));
+    assignments
+        .methods
+        .into_iter()
+        .filter_map(|(name, method)| Some((name, method?)))
+        .collect()
+}
+
+struct LoggerAssignments {
+    // `None` for names assigned something other than a logger method, or more than once
+    methods: HashMap<String, Option<Expr>>,
+}
+
+impl<'a> Visitor<'a> for LoggerAssignments {
+    fn visit_stmt(&mut self, stmt: &'a Stmt) {
+        if let StmtKind::Assign { targets, value, .. } = &stmt.node {
+            let method = logger_method(value);
+            for target in targets {
+                if let ExprKind::Name { id, .. } = &target.node {
+                    self.methods
+                        .entry(id.clone())
+                        .and_modify(|previous| *previous = None)
+                        .or_insert_with(|| method.clone());
+                }
+            }
+        }
+        walk_stmt(self, stmt);
+    }
+
+    // Assignments are statements, so there's nothing to find in expressions
+    fn visit_expr(&mut self, _expr: &'a Expr) {}
+}
+
+/// The logger method an assigned value refers to, like `logger.error`, also when it's
+/// wrapped in `functools.partial` without any arguments bound
+fn logger_method(value: &Expr) -> Option<Expr> {
+    match &value.node {
+        ExprKind::Attribute { attr, .. } if LogLevel::maybe_from_str(attr).is_some() => {
+            Some(value.clone())
+        }
+        ExprKind::Call {
+            func,
+            args,
+            keywords,
+        } if args.len() == 1 && keywords.is_empty() => {
+            let is_partial = match &func.node {
+                ExprKind::Name { id, .. } => id == "partial",
+                ExprKind::Attribute { value, attr, .. } => {
+                    attr == "partial"
+                        && matches!(&value.node, ExprKind::Name { id, .. } if id == "functools")
+                }
+                _ => false,
+            };
+            if is_partial {
+                logger_method(&args[0])
+            } else {
+                None
+            }
+        }
+        _ => None,
+    }
+}
+
+/// The name an expression like `a.b(c)[d].e` starts from
+fn root_name(expr: &Expr) -> Option<&str> {
+    match &expr.node {
+        ExprKind::Name { id, .. } => Some(id),
+        ExprKind::Attribute { value, .. }
+        | ExprKind::Subscript { value, .. }
+        | ExprKind::Call { func: value, .. } => root_name(value),
+        _ => None,
+    }
+}
+
+/// Whether a receiver is looked up by index anywhere along its chain, like `loggers[0].app`
+fn contains_subscript(expr: &Expr) -> bool {
+    match &expr.node {
+        ExprKind::Subscript { .. } => true,
+        ExprKind::Attribute { value, .. } | ExprKind::Call { func: value, .. } => {
+            contains_subscript(value)
+        }
+        _ => false,
+    }
+}
+
+/// The name a method is called on, like `metrics` in `self.metrics.info()`
+fn receiver_name(expr: &Expr) -> Option<&str> {
+    match &expr.node {
+        ExprKind::Name { id, .. } => Some(id),
+        ExprKind::Attribute { attr, .. } => Some(attr),
+        _ => None,
+    }
+}
+
+/// Arguments appearing more than once, like `x` in `f"{x} and {x}"` or `"{} {}".format(x, x)`,
+/// listed once each
+fn duplicates(arguments: &[String]) -> Vec<String> {
+    let mut seen = HashSet::new();
+    let mut duplicates = vec![];
+    for argument in arguments {
+        if !seen.insert(argument) && !duplicates.contains(argument) {
+            duplicates.push(argument.clone());
+        }
+    }
+    duplicates
+}
+
+/// Check whether a call is to the `print` builtin
+fn is_print(func: &Expr) -> bool {
+    matches!(&func.node, ExprKind::Name { id, .. } if id == "print")
+}
+
+/// Escape backslashes and any occurrences of the quote character in a string's content,
+/// so it can be wrapped in that quote character.
+pub fn escape_quotes(content: &str, quote: char) -> String {
+    content
+        .replace('\\', "\\\\")
+        .replace(quote, &format!("\\{quote}"))
+}
+
+/// Whether a string's content can be written as a raw string with this quote character.
+/// Raw strings can't escape anything, so they can't contain the quote character or a
+/// newline, or end with a backslash.
+fn is_raw_safe(content: &str, quote: char) -> bool {
+    !content.contains([quote, '\n', '\r']) && !content.ends_with('\\')
+}
+
+/// How the `%` signs in a message's text are escaped, once the logger formats the message
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum PercentEscape {
+    /// The message isn't formatted, so they're kept as they are
+    Keep,
+    /// The message wasn't formatted before, so every `%` is literal, also in `%%` or `%s`
+    All,
+    /// Arguments already followed the message, so conversions, like a literal `%s`, take those
+    /// and are kept. Any other `%`, like the one in `100%`, is doubled.
+    Literal,
+}
+
+/// Escape the `%` signs in text that ends up in a message the logger formats
+pub(crate) fn escape_percent(re: &Regex, content: &str, escape: PercentEscape) -> String {
+    match escape {
+        PercentEscape::Keep => return content.to_string(),
+        PercentEscape::All => return content.replace('%', "%%"),
+        PercentEscape::Literal => (),
+    }
+    let mut escaped = String::with_capacity(content.len());
+    let mut rest = content;
+    while let Some(index) = rest.find('%') {
+        escaped.push_str(&rest[..index]);
+        rest = &rest[index..];
+        if let Some(conversion) = re.find(rest) {
+            escaped.push_str(conversion.as_str());
+            rest = &rest[conversion.end()..];
+        } else {
+            escaped.push_str("%%");
+            rest = &rest[1..];
+        }
+    }
+    escaped.push_str(rest);
+    escaped
+}
+
+/// The printf-style conversions in text, not counting escaped `%%`
+fn percent_conversions<'a>(re: &Regex, content: &'a str) -> Vec<&'a str> {
+    let mut conversions = vec![];
+    let mut rest = content;
+    while let Some(index) = rest.find('%') {
+        rest = &rest[index..];
+        match re.find(rest) {
+            Some(conversion) => {
+                if conversion.as_str() != "%%" {
+                    conversions.push(conversion.as_str());
+                }
+                rest = &rest[conversion.end()..];
+            }
+            None => rest = &rest[1..],
+        }
+    }
+    conversions
+}
+
+/// Render a constant as an argument, wrapping strings and bytes in the quote character
+pub(crate) fn constant_to_argument(constant: &Constant, quote: char) -> String {
+    match constant {
+        Constant::Str(value) => format!("{quote}{}{quote}", escape_quotes(value, quote)),
+        Constant::Bytes(value) => bytes_to_string(value, quote),
+        _ => constant_to_string(constant.clone()),
+    }
+}
+
+/// Render bytes as a literal wrapped in the quote character. Bytes needn't be valid
+/// UTF-8, so anything that isn't printable ASCII is escaped, like `repr()` does.
+fn bytes_to_string(value: &[u8], quote: char) -> String {
+    let mut literal = format!("b{quote}");
+    for &byte in value {
+        match byte {
+            b'\\' => literal.push_str("\\\\"),
+            b'\t' => literal.push_str("\\t"),
+            b'\n' => literal.push_str("\\n"),
+            b'\r' => literal.push_str("\\r"),
+            _ if char::from(byte) == quote => {
+                literal.push('\\');
+                literal.push(quote);
+            }
+            0x20..=0x7e => literal.push(char::from(byte)),
+            _ => literal.push_str(&format!("\\x{byte:02x}")),
+        }
+    }
+    literal.push(quote);
+    literal
+}
+
 pub fn constant_to_string(constant: Constant) -> String {
     match constant {
         Constant::None => "None".to_string(),
-        Constant::Bool(value) => value.to_string(),
+        Constant::Bool(value) => if value { "True" } else { "False" }.to_string(),
         Constant::Str(value) => value,
-        Constant::Bytes(value) => format!("b\"{}\"", String::from_utf8_lossy(&value)),
+        Constant::Bytes(value) => bytes_to_string(&value, '"'),
         Constant::Int(value) => value.to_string(),
         Constant::Float(value) => value.to_string(),
         Constant::Ellipsis => "...".to_string(),
@@ -184,3 +1297,12 @@ pub fn operator_to_string(operator: &Operator) -> String {
         Operator::FloorDiv => "//".to_owned(),
     }
 }
+
+pub fn unary_operator_to_string(operator: &Unaryop) -> String {
+    match operator {
+        Unaryop::Invert => "~".to_owned(),
+        Unaryop::Not => "not ".to_owned(),
+        Unaryop::UAdd => "+".to_owned(),
+        Unaryop::USub => "-".to_owned(),
+    }
+}
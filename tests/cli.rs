@@ -0,0 +1,652 @@
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Output, Stdio};
+
+/// Write a Python file to a scratch directory unique to the calling test
+fn write_file(test_name: &str, content: &str) -> PathBuf {
+    let dir = std::env::temp_dir()
+        .join("printf-log-formatter-tests")
+        .join(test_name);
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("test.py");
+    fs::write(&path, content).unwrap();
+    path
+}
+
+fn output(args: &[&str], path: &PathBuf) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_printf-log-formatter"))
+        .args(args)
+        .arg(path)
+        .output()
+        .unwrap()
+}
+
+fn run(args: &[&str], path: &PathBuf) -> i32 {
+    output(args, path).status.code().unwrap()
+}
+
+#[test]
+fn test_exit_code_nothing_changed() {
+    let path = write_file("nothing_changed", "logger.error('%s', x)\n");
+    assert_eq!(run(&[], &path), 0);
+}
+
+#[test]
+fn test_exit_code_changed() {
+    let path = write_file("changed", "logger.error(f'{x}')\n");
+    assert_eq!(run(&[], &path), 1);
+    assert_eq!(
+        fs::read_to_string(&path).unwrap(),
+        "logger.error('%s', x)\n"
+    );
+
+    // Once fixed, there's nothing left to change
+    assert_eq!(run(&[], &path), 0);
+}
+
+#[test]
+fn test_exit_code_check_does_not_write() {
+    let path = write_file("check", "logger.error(f'{x}')\n");
+    assert_eq!(run(&["--no-write"], &path), 1);
+    assert_eq!(fs::read_to_string(&path).unwrap(), "logger.error(f'{x}')\n");
+}
+
+#[test]
+fn test_exit_code_parse_failure() {
+    let path = write_file("parse_failure", "logger.error(f'{x}'\n");
+    assert_eq!(run(&[], &path), 0);
+    assert_eq!(run(&["--error-on-parse-failure"], &path), 2);
+}
+
+#[test]
+fn test_parse_failure_notes_quote_reuse() {
+    let note = "f-strings reusing their quotes inside placeholders";
+    let path = write_file("quote_reuse", "logger.error(f\"{d[\"key\"]}\")\n");
+    let result = output(&[], &path);
+    assert!(String::from_utf8_lossy(&result.stderr).contains(note));
+
+    // Other broken f-strings don't get the note
+    let path = write_file("broken_fstring", "logger.error(f\"{}\")\n");
+    let result = output(&[], &path);
+    let stderr = String::from_utf8_lossy(&result.stderr);
+    assert!(stderr.starts_with("Failed to parse"));
+    assert!(!stderr.contains(note));
+}
+
+#[test]
+fn test_quiet() {
+    let content = "logger.error(f\"it's {x}\")\nlogger.error(f\"{a} isn't {b}'s\")\n";
+
+    let path = write_file("quiet", content);
+    let result = output(&["--quiet", "--quote", "single"], &path);
+    assert_eq!(result.status.code(), Some(1));
+    assert_eq!(String::from_utf8_lossy(&result.stdout), "");
+    assert_eq!(String::from_utf8_lossy(&result.stderr), "");
+
+    let path = write_file("quiet_check", content);
+    let result = output(&["--quiet", "--check", "--quote", "single"], &path);
+    assert_eq!(result.status.code(), Some(1));
+    assert_eq!(String::from_utf8_lossy(&result.stdout), "");
+    assert_eq!(String::from_utf8_lossy(&result.stderr), "");
+
+    let path = write_file("quiet_parse_failure", "logger.error(f'{x}'\n");
+    let result = output(&["--quiet"], &path);
+    assert_eq!(result.status.code(), Some(0));
+    assert_eq!(String::from_utf8_lossy(&result.stderr), "");
+
+    // Without --quiet, the report and notes are printed
+    let path = write_file("not_quiet", content);
+    let result = output(&["--check", "--quote", "single"], &path);
+    assert_ne!(String::from_utf8_lossy(&result.stdout), "");
+    let path = write_file("not_quiet_parse_failure", "logger.error(f'{x}'\n");
+    let result = output(&[], &path);
+    assert_ne!(String::from_utf8_lossy(&result.stderr), "");
+}
+
+#[test]
+fn test_format_on_fstring_warns() {
+    let path = write_file(
+        "format_on_fstring",
+        "logger.error(f'{prefix} {{}}'.format(x))\n",
+    );
+    let result = output(&[], &path);
+    assert_eq!(result.status.code(), Some(0));
+    assert_eq!(
+        String::from_utf8_lossy(&result.stderr),
+        format!(
+            "Skipping the logger in `{}` line 1, since it calls .format() on an f-string\n",
+            path.display()
+        )
+    );
+}
+
+#[test]
+fn test_bytes_format_target_is_skipped_quietly() {
+    let content = "logger.error(b'{}'.format(x))\n";
+    let path = write_file("bytes_format_target", content);
+    let result = output(&[], &path);
+    assert_eq!(result.status.code(), Some(0));
+    assert_eq!(String::from_utf8_lossy(&result.stderr), "");
+    assert_eq!(fs::read_to_string(&path).unwrap(), content);
+}
+
+#[test]
+fn test_include_and_exclude() {
+    let content = "logger.error(f'{x}')\n";
+    let tree = |test_name: &str| {
+        let dir = std::env::temp_dir()
+            .join("printf-log-formatter-tests")
+            .join(test_name);
+        for file in ["src/pkg/a.py", "scripts/b.py"] {
+            let path = dir.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, content).unwrap();
+        }
+        dir
+    };
+
+    let dir = tree("include");
+    assert_eq!(run(&["--include", "src/**/*.py"], &dir), 1);
+    assert_eq!(
+        fs::read_to_string(dir.join("src/pkg/a.py")).unwrap(),
+        "logger.error('%s', x)\n"
+    );
+    assert_eq!(
+        fs::read_to_string(dir.join("scripts/b.py")).unwrap(),
+        content
+    );
+
+    // Excluding wins over including
+    let dir = tree("include_and_exclude");
+    assert_eq!(run(&["--include", "**/*.py", "--exclude", "a.py"], &dir), 1);
+    assert_eq!(
+        fs::read_to_string(dir.join("src/pkg/a.py")).unwrap(),
+        content
+    );
+    assert_eq!(
+        fs::read_to_string(dir.join("scripts/b.py")).unwrap(),
+        "logger.error('%s', x)\n"
+    );
+}
+
+#[test]
+fn test_exit_code_hard_error() {
+    // Files that can't be read are reported, and the others still handled
+    let path = write_file("hard_error", "logger.error(f'{x}')\n");
+    let missing = path.with_file_name("missing.py");
+    let result = output(&[missing.to_str().unwrap()], &path);
+    assert_eq!(result.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&result.stderr)
+        .starts_with(&format!("Failed to read `{}`", missing.display())));
+    assert_eq!(
+        fs::read_to_string(&path).unwrap(),
+        "logger.error('%s', x)\n"
+    );
+
+    // Invalid arguments
+    assert_eq!(run(&["--log-level", "loud"], &path), 2);
+}
+
+#[test]
+fn test_write_failure_is_not_fatal() {
+    // A file that can't be written is reported, and the others still written
+    let dir = std::env::temp_dir()
+        .join("printf-log-formatter-tests")
+        .join("write_failure");
+    let _ = fs::remove_dir_all(&dir);
+    let (input, output_dir) = (dir.join("in"), dir.join("out"));
+    fs::create_dir_all(&input).unwrap();
+    fs::write(input.join("a.py"), "logger.error(f'{x}')\n").unwrap();
+    fs::write(input.join("b.py"), "logger.error(f'{y}')\n").unwrap();
+    // A directory where a file should be written can't be replaced, even by root
+    let mirrored = output_dir.clone();
+    fs::create_dir_all(mirrored.join("a.py")).unwrap();
+
+    let result = output(&["--output-dir", output_dir.to_str().unwrap()], &input);
+    assert_eq!(result.status.code(), Some(2));
+    assert!(
+        String::from_utf8_lossy(&result.stderr).starts_with(&format!(
+            "Failed to write `{}`",
+            mirrored.join("a.py").display()
+        ))
+    );
+    assert_eq!(
+        fs::read_to_string(mirrored.join("b.py")).unwrap(),
+        "logger.error('%s', y)\n"
+    );
+}
+
+#[test]
+fn test_format_only_normalize_flavor() {
+    let path = write_file("format_only_normalize_flavor", "logger.error(f'{x}')\n");
+    let args = ["--format-only-normalize", "--logger-flavor"];
+    assert_eq!(run(&[&args[..], &["loguru"]].concat(), &path), 2);
+    assert_eq!(run(&[&args[..], &["stdlib"]].concat(), &path), 1);
+    assert_eq!(
+        fs::read_to_string(&path).unwrap(),
+        "logger.error('{}'.format(x))\n"
+    );
+}
+
+#[test]
+fn test_print_config() {
+    let output = Command::new(env!("CARGO_BIN_EXE_printf-log-formatter"))
+        .args([
+            "--print-config",
+            "--log-level",
+            "info",
+            "--levels",
+            "debug,warning",
+        ])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0));
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.starts_with("# printf-log-formatter "));
+    let lines = stdout.lines().collect::<Vec<_>>();
+    assert!(lines.contains(&"log-level = \"info\""));
+    assert!(lines.contains(&"levels = [\"debug\", \"warning\"]"));
+    assert!(lines.contains(&"placeholder = \"%s\""));
+    assert!(lines.contains(&"filenames = []"));
+    // Unset options are left out
+    assert!(!stdout.contains("line-length"));
+}
+
+#[test]
+fn test_print_config_with_pyproject() {
+    let dir = std::env::temp_dir()
+        .join("printf-log-formatter-tests")
+        .join("pyproject");
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(
+        dir.join("pyproject.toml"),
+        "[tool.printf-log-formatter]\nlog-level = \"warning\"\nline-length = 88\n",
+    )
+    .unwrap();
+
+    // Options on the command line override the ones in `pyproject.toml`
+    let output = Command::new(env!("CARGO_BIN_EXE_printf-log-formatter"))
+        .args(["--print-config", "--log-level", "info"])
+        .current_dir(&dir)
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let lines = stdout.lines().collect::<Vec<_>>();
+    assert!(lines.contains(&"log-level = \"info\""));
+    assert!(lines.contains(&"line-length = 88"));
+}
+
+#[test]
+fn test_warn_below_threshold() {
+    let content = concat!(
+        "logger.debug(f'{x}')\n",
+        "logger.info('plain')\n",
+        "logger.debug(msg='{}'.format(y))\n",
+        "logger.debug(f'{z}')  # printf: skip\n",
+        "logger.error(f'{x}')\n",
+    );
+    let path = write_file("warn_below_threshold", content);
+    let result = output(&["--warn-below-threshold", "--check"], &path);
+    assert_eq!(result.status.code(), Some(1));
+    let warning = |line: usize| {
+        format!(
+            "`logger.debug` in `{}` line {line} is below the log level, \
+             so its message is still formatted eagerly\n",
+            path.display()
+        )
+    };
+    assert_eq!(
+        String::from_utf8_lossy(&result.stderr),
+        warning(1) + &warning(3)
+    );
+    // Only a warning
+    assert_eq!(fs::read_to_string(&path).unwrap(), content);
+
+    let result = output(&["--check"], &path);
+    assert_eq!(String::from_utf8_lossy(&result.stderr), "");
+
+    // Levels left out of `--levels` aren't necessarily below anything
+    let result = output(
+        &["--warn-below-threshold", "--check", "--levels", "error"],
+        &path,
+    );
+    assert_eq!(
+        String::from_utf8_lossy(&result.stderr).lines().next(),
+        Some(
+            format!(
+                "`logger.debug` in `{}` line 1 isn't at one of the `--levels` handled, \
+                 so its message is still formatted eagerly",
+                path.display()
+            )
+            .as_str()
+        )
+    );
+}
+
+#[test]
+fn test_max_failures() {
+    let dir = std::env::temp_dir()
+        .join("printf-log-formatter-tests")
+        .join("max_failures");
+    fs::create_dir_all(&dir).unwrap();
+    for name in ["a.py", "b.py", "c.py"] {
+        fs::write(dir.join(name), "logger.error(f'{x}'\n").unwrap();
+    }
+
+    let result = output(&["--max-failures", "2"], &dir);
+    assert_eq!(result.status.code(), Some(2));
+    let stderr = String::from_utf8_lossy(&result.stderr);
+    assert!(stderr.ends_with("Stopping after 2 files failed to parse\n"));
+
+    // Unlimited by default, and parse failures alone aren't hard errors
+    assert_eq!(run(&["--max-failures", "4"], &dir), 0);
+    assert_eq!(run(&[], &dir), 0);
+}
+
+#[test]
+fn test_output_dir() {
+    let dir = std::env::temp_dir()
+        .join("printf-log-formatter-tests")
+        .join("output_dir");
+    let _ = fs::remove_dir_all(&dir);
+    let (input, output_dir) = (dir.join("in"), dir.join("out"));
+    fs::create_dir_all(input.join("pkg")).unwrap();
+    fs::write(input.join("pkg/a.py"), "logger.error(f'{x}')\n").unwrap();
+    fs::write(input.join("b.py"), "logger.error('%s', x)\n").unwrap();
+
+    let output_dir_arg = output_dir.to_str().unwrap();
+    assert_eq!(run(&["--output-dir", output_dir_arg], &input), 1);
+    // Inputs are left alone, and only changed files are written
+    assert_eq!(
+        fs::read_to_string(input.join("pkg/a.py")).unwrap(),
+        "logger.error(f'{x}')\n"
+    );
+    // Paths are mirrored relative to the directory searched
+    assert_eq!(
+        fs::read_to_string(output_dir.join("pkg/a.py")).unwrap(),
+        "logger.error('%s', x)\n"
+    );
+    assert!(!output_dir.join("b.py").exists());
+
+    assert_eq!(
+        run(
+            &["--output-dir", output_dir_arg, "--copy-unchanged"],
+            &input
+        ),
+        1
+    );
+    assert_eq!(
+        fs::read_to_string(output_dir.join("b.py")).unwrap(),
+        "logger.error('%s', x)\n"
+    );
+
+    // An output directory inside the input isn't searched on later runs
+    let inner_arg = input.join("converted").to_string_lossy().into_owned();
+    for _ in 0..2 {
+        run(&["--output-dir", &inner_arg, "--copy-unchanged"], &input);
+    }
+    assert!(input.join("converted/pkg/a.py").exists());
+    assert!(!input.join("converted/converted").exists());
+
+    // Files that would be written to the same path are refused
+    fs::create_dir_all(dir.join("other")).unwrap();
+    fs::write(dir.join("other/b.py"), "logger.error(f'{y}')\n").unwrap();
+    let other_arg = dir.join("other").to_string_lossy().into_owned();
+    let result = output(&["--output-dir", output_dir_arg, &other_arg], &input);
+    assert_eq!(result.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&result.stderr).contains(&format!(
+        "would both be written to `{}`",
+        output_dir.join("b.py").display()
+    )));
+    assert_eq!(
+        fs::read_to_string(dir.join("other/b.py")).unwrap(),
+        "logger.error(f'{y}')\n"
+    );
+}
+
+#[test]
+fn test_progress_is_hidden_without_a_terminal() {
+    // Output is captured here, so stderr isn't a terminal
+    let path = write_file("progress", "logger.error(f'{x}')\n");
+    let result = output(&["--progress"], &path);
+    assert_eq!(result.status.code(), Some(1));
+    assert_eq!(String::from_utf8_lossy(&result.stderr), "");
+}
+
+#[test]
+fn test_mixed_numbering_is_skipped() {
+    let content = "logger.error('{} {0}'.format(x))\n";
+    let path = write_file("mixed_numbering", content);
+    let result = output(&[], &path);
+    assert_eq!(result.status.code(), Some(0));
+    assert_eq!(
+        String::from_utf8_lossy(&result.stderr),
+        format!(
+            "Skipping the logger in `{}` line 1, \
+             since its message mixes automatic and manual field numbering\n",
+            path.display()
+        )
+    );
+    assert_eq!(fs::read_to_string(&path).unwrap(), content);
+}
+
+#[test]
+fn test_message_choice_with_different_arguments() {
+    let content = "logger.error(f'{x}' if v else '{}'.format(y))\n";
+    let path = write_file("message_choice", content);
+    let result = output(&[], &path);
+    assert_eq!(result.status.code(), Some(0));
+    assert_eq!(
+        String::from_utf8_lossy(&result.stderr),
+        format!(
+            "Skipping the logger in `{}` line 1, since both messages need the same arguments\n",
+            path.display()
+        )
+    );
+    assert_eq!(fs::read_to_string(&path).unwrap(), content);
+}
+
+#[test]
+fn test_skipped_expressions_are_not_errors() {
+    // Expressions we can't convert are expected, so there's no issue to open
+    let content = "logger.error(f'{(lambda: 1)()}')\nlogger.error(TEMPLATE.format(x))\n";
+    let path = write_file("skipped_expressions", content);
+    let result = output(&["--issue-url", "https://git.example.com/issues"], &path);
+    assert_eq!(result.status.code(), Some(0));
+    assert_eq!(
+        String::from_utf8_lossy(&result.stderr),
+        format!(
+            "Skipping the logger in `{0}` line 1, \
+             since it contains an expression that can't be converted\n\
+             Skipping the logger in `{0}` line 2, since its message doesn't start with a string\n",
+            path.display()
+        )
+    );
+    assert_eq!(fs::read_to_string(&path).unwrap(), content);
+}
+
+#[test]
+fn test_preview() {
+    let content = "logger.error(f'{x}')\n";
+    let path = write_file("preview", content);
+    let result = output(&["--preview"], &path);
+    assert_eq!(result.status.code(), Some(1));
+    assert_eq!(
+        String::from_utf8_lossy(&result.stdout),
+        format!("at {}:1, `f'{{x}}'` → `'%s', x`\n", path.display())
+    );
+    // Nothing is written
+    assert_eq!(fs::read_to_string(&path).unwrap(), content);
+}
+
+#[test]
+fn test_quote_fallback_is_verbose() {
+    let path = write_file("quote_fallback", "logger.error(f'\"{x}\" is \"{y}\"')\n");
+    let result = output(&["--quote", "double"], &path);
+    assert_eq!(result.status.code(), Some(1));
+    assert!(result.stderr.is_empty());
+    assert_eq!(
+        fs::read_to_string(&path).unwrap(),
+        "logger.error('\"%s\" is \"%s\"', x, y)\n"
+    );
+
+    fs::write(&path, "logger.error(f'\"{x}\" is \"{y}\"')\n").unwrap();
+    let result = output(&["--quote", "double", "--verbose"], &path);
+    assert_eq!(
+        String::from_utf8_lossy(&result.stderr),
+        format!(
+            "Using ' quotes for the logger in `{}` line 1 to avoid escaping\nFixed `{}`\n",
+            path.display(),
+            path.display()
+        )
+    );
+}
+
+#[test]
+fn test_parse_fallback_is_noted_once() {
+    let path = write_file("parse_fallback_verbose", "logger.error(f'{len(a or b)}')\n");
+    let result = output(&["--force-parse-fallback", "--verbose"], &path);
+    assert_eq!(result.status.code(), Some(1));
+    assert_eq!(
+        String::from_utf8_lossy(&result.stderr),
+        format!(
+            "Copied `len(a or b)` from the source of `{0}` line 1\nFixed `{0}`\n",
+            path.display()
+        )
+    );
+}
+
+#[test]
+fn test_json_report() {
+    // Lines come from before the file is written
+    let path = write_file("json_report", "x = 1\nlogger.error(f'{x}')\ny = 2\nz = 3\n");
+    let result = output(&["--output-format", "json", "--report-context", "1"], &path);
+    assert_eq!(result.status.code(), Some(1));
+    let report = String::from_utf8_lossy(&result.stdout);
+    assert!(report.contains(
+        "\"context_before\": [\"x = 1\"], \"source_lines\": [\"logger.error(f'{x}')\"], \"replacement_lines\": [\"logger.error('%s', x)\"], \"context_after\": [\"y = 2\"]"
+    ));
+    assert_eq!(
+        fs::read_to_string(&path).unwrap(),
+        "x = 1\nlogger.error('%s', x)\ny = 2\nz = 3\n"
+    );
+}
+
+#[test]
+fn test_profile() {
+    let path = write_file("profile", "logger.error(f'{x}')\n");
+    let result = output(&["--profile"], &path);
+    assert_eq!(result.status.code(), Some(1));
+    let stderr = String::from_utf8_lossy(&result.stderr);
+    let labels = stderr
+        .lines()
+        .skip_while(|line| *line != "Profile:")
+        .map(|line| line.split_whitespace().next().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        labels,
+        ["Profile:", "read", "parse", "detect", "apply", "write", "total"]
+    );
+    assert!(output(&[], &path).stderr.is_empty());
+}
+
+#[test]
+fn test_centered_spec_warns() {
+    let path = write_file(
+        "centered_spec",
+        "logger.error(f'{a:^10}')\nlogger.error('{:<5} {:^3}'.format(b, c))\n",
+    );
+    let result = output(&[], &path);
+    assert_eq!(result.status.code(), Some(1));
+    let warning = |line: usize, spec: &str| {
+        format!(
+            "Centered values can't be expressed with printf-style formatting, \
+             so `{{:{spec}}}` in `{}` line {line} is replaced with `%s`\n",
+            path.display()
+        )
+    };
+    assert_eq!(
+        String::from_utf8_lossy(&result.stderr),
+        warning(1, "^10") + &warning(2, "^3")
+    );
+    assert_eq!(
+        fs::read_to_string(&path).unwrap(),
+        "logger.error('%s', a)\nlogger.error('%-5s %s', b, c)\n"
+    );
+}
+
+#[test]
+fn test_rename_warn_on_another_line() {
+    let path = write_file("rename_warn", "logger.warn(\n    f'{a}',\n)\n");
+    let result = output(&["--rename-warn", "-l", "warning"], &path);
+    assert_eq!(result.status.code(), Some(1));
+    assert_eq!(String::from_utf8_lossy(&result.stderr), "");
+    assert_eq!(
+        fs::read_to_string(&path).unwrap(),
+        "logger.warning(\n    '%s', a,\n)\n"
+    );
+}
+
+#[test]
+fn test_stdin() {
+    let stdin_with = |args: &[&str], content: &str| {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_printf-log-formatter"))
+            .args(["--stdin-filename", "app.py"])
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        child
+            .stdin
+            .take()
+            .unwrap()
+            .write_all(content.as_bytes())
+            .unwrap();
+        child.wait_with_output().unwrap()
+    };
+    let stdin = |content: &str| stdin_with(&[], content);
+
+    let result = stdin("x = 1\nlogger.error(f'{x}')\n");
+    assert_eq!(result.status.code(), Some(1));
+    assert_eq!(
+        String::from_utf8_lossy(&result.stdout),
+        "x = 1\nlogger.error('%s', x)\n"
+    );
+
+    // Unchanged content is still printed
+    let result = stdin("logger.error('%s', x)\n");
+    assert_eq!(result.status.code(), Some(0));
+    assert_eq!(
+        String::from_utf8_lossy(&result.stdout),
+        "logger.error('%s', x)\n"
+    );
+
+    // Messages name the file given
+    let result = stdin("logger.error(f'{x}'\n");
+    assert_eq!(result.status.code(), Some(0));
+    assert_eq!(
+        String::from_utf8_lossy(&result.stderr),
+        "Failed to parse `app.py`\n"
+    );
+    assert_eq!(
+        String::from_utf8_lossy(&result.stdout),
+        "logger.error(f'{x}'\n"
+    );
+
+    // Unless parse failures are errors
+    let result = stdin_with(&["--error-on-parse-failure"], "logger.error(f'{x}'\n");
+    assert_eq!(result.status.code(), Some(2));
+    assert_eq!(
+        String::from_utf8_lossy(&result.stderr),
+        "Failed to parse `app.py`\n"
+    );
+    assert!(result.stdout.is_empty());
+
+    let result = stdin_with(&["--check"], "logger.error(f'{x}')\n");
+    assert_eq!(result.status.code(), Some(2));
+}